@@ -0,0 +1,26 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+
+#[derive(Debug, TypeUuid)]
+#[uuid = "6b1e3c1a-58d2-4f3e-9a47-2f0c8d9e7b15"]
+pub struct BirdsongScript {
+    pub source: String,
+}
+
+#[derive(Default)]
+pub struct BirdsongScriptLoader;
+
+impl AssetLoader for BirdsongScriptLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?.to_string();
+            load_context.set_default_asset(LoadedAsset::new(BirdsongScript{source}));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["birdsong"]
+    }
+}
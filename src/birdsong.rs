@@ -1,7 +1,16 @@
-use bevy::{prelude::*, text::Text2dBounds};
+use bevy::{prelude::*, asset::LoadState, audio::AudioSink, ecs::query::WorldQuery, text::{DefaultTextPipeline, Text2dBounds, Text2dSize}, window::WindowId};
 use std::collections::HashMap;
 use std::time::Duration;
-use crate::components::{DialogueBoxComponent, VoiceComponent, PortraitComponent, BackgroundComponent, ChoiceCursorComponent, ChoiceItemComponent};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::asset::{BirdsongScript, BirdsongScriptLoader};
+use crate::command::{BirdsongCommands, CommandStatus};
+use crate::components::{DialogueBoxComponent, GlyphComponent, PanelComponent, NameplateComponent, StageActorComponent, VoiceComponent, PortraitComponent, BackgroundComponent, ChoiceCursorComponent, ChoiceItemComponent};
+use crate::events::{BirdsongDiagnosticEvent, DialogueStartedEvent, LineShownEvent, TypewriterFinishedEvent, ChoicePresentedEvent, ChoiceMadeEvent, DialogueEndedEvent};
+use crate::expr::{Expr, Value};
+use crate::panel::{nine_slice_atlas, spawn_nine_slice};
+use crate::input::{BirdsongAction, BirdsongActionEvent, BirdsongInput, InputDevices, ALL_ACTIONS};
+use crate::rng::BirdsongRng;
+use crate::script::{parse_script, BackgroundFit, BackgroundLayer, BlipDecl, BlipMode, ControlTag, Entry, HideTarget, RunStyle, Setting, StageSlot, StyleRun, Transition, TransitionKind, TypewriterControl};
 
 const DEFAULT_FONT_PATH: &str = "fonts/PlayfairDisplay-Regular.ttf";
 const DEFAULT_FONT_SIZE: f32 = 45.;
@@ -9,12 +18,36 @@ const DEFAULT_TEXT_COLOR: Color = Color::rgba(1., 1., 1., 1.);
 const DEFAULT_CURSOR_PATH: &str = "images/cursor.png";
 const DEFAULT_BOX_SIZE: Vec2 = Vec2::new(350., 600.);
 const DEFAULT_BOX_POSITION: Vec3 = Vec3::new(-600., 100., 1.);
-const DEFAULT_TEXT_SPEED: f32 = 100.;
+const DEFAULT_TEXT_SPEED: f32 = 100.; // Characters per second
+const DEFAULT_FADE_IN: f32 = 0.; // Seconds
 const DEFAULT_VOICE_FREQUENCY: f32 = 0.1; 
 const DEFAULT_CHOICE_SPACING: f32 = 40.;
 const DEFAULT_CHOICE_INDENT: f32 = 25.;
 const DEFAULT_CURSOR_OFFSET: f32 = 16.;
 const DEFAULT_PORTRAIT_POSITION: Vec3 = Vec3::new(-425., 225., 1.);
+const DEFAULT_PANEL_PADDING: Vec2 = Vec2::new(20., 20.);
+const DEFAULT_NAMEPLATE_OFFSET: Vec3 = Vec3::new(-110., -140., 0.1);
+const DEFAULT_NAMEPLATE_SIZE: Vec2 = Vec2::new(220., 60.);
+const DEFAULT_STAGE_DIM_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 1.);
+const STAGE_LEFT: Vec3 = Vec3::new(-400., 0., 0.5);
+const STAGE_CENTER: Vec3 = Vec3::new(0., 0., 0.5);
+const STAGE_RIGHT: Vec3 = Vec3::new(400., 0., 0.5);
+// A background being replaced moves this far back
+const PREVIOUS_BACKGROUND_DEPTH: f32 = 0.01;
+// Same for the side portrait
+const PREVIOUS_PORTRAIT_DEPTH: f32 = 0.01;
+// Panels sit this far behind their text
+const PANEL_DEPTH: f32 = 0.1;
+// Text effects, with offsets relative to the font size
+const SHAKE_RATE: f32 = 20.;
+const SHAKE_AMPLITUDE: f32 = 0.04;
+const WAVE_SPEED: f32 = 6.;
+const WAVE_SPACING: f32 = 0.5;
+const WAVE_AMPLITUDE: f32 = 0.08;
+const RAINBOW_SPEED: f32 = 180.; // Degrees per second
+const RAINBOW_SPACING: f32 = 20.;
+// Graphemes starting with one of these count as vowels for syllable blips.
+const VOWELS: &str = "aeiouyàáâäèéêëìíîïòóôöùúûü";
 
 pub struct BirdsongPlugin;
 
@@ -28,54 +61,299 @@ impl Plugin for BirdsongPlugin {
             .add_startup_system_to_stage(INIT_SETTINGS, birdsong_setup_default_settings_system)
             .add_startup_stage_after(INIT_SETTINGS, INIT_SYSTEM,  SystemStage::single_threaded())
             .add_startup_system_to_stage(INIT_SYSTEM, birdsong_setup_system)
+            .add_event::<BirdsongDiagnosticEvent>()
+            .add_event::<DialogueStartedEvent>()
+            .add_event::<LineShownEvent>()
+            .add_event::<TypewriterFinishedEvent>()
+            .add_event::<ChoicePresentedEvent>()
+            .add_event::<ChoiceMadeEvent>()
+            .add_event::<DialogueEndedEvent>()
+            .add_event::<BirdsongActionEvent>()
+            .init_resource::<BirdsongCommands>()
+            .init_resource::<BirdsongInput>()
+            .add_asset::<BirdsongScript>()
+            .init_asset_loader::<BirdsongScriptLoader>()
+            .add_system(birdsong_init_runner_system)
+            .add_system(birdsong_apply_requests_system)
+            .add_system(birdsong_load_script_asset_system)
+            .add_system(birdsong_reload_script_asset_system)
             .add_system(birdsong_parse_script_system)
             .add_system(birdsong_handle_input_system)
+            .add_system(birdsong_handle_pointer_system)
             .add_system(birdsong_process_entry_system)
+            .add_system(birdsong_run_command_system.exclusive_system())
             .add_system(birdsong_update_dialoguebox_system)
+            .add_system(birdsong_update_glyphs_system)
             .add_system(birdsong_update_choices_system)
             .add_system(birdsong_update_background_system)
             .add_system(birdsong_update_actor_system)
-            .add_system(birdsong_update_exposed_line_system);
+            .add_system(birdsong_update_stage_system)
+            .add_system(birdsong_update_panels_system)
+            .add_system(birdsong_update_exposed_line_system)
+            .add_system(birdsong_teardown_system);
         }
 }
 
 pub struct Birdsong {
-    script_data: ScriptData,
-    curr_line: usize
+    main_runner: Entity,
+    requests: Vec<RunnerRequest>,
+    statuses: HashMap<Entity, RunnerStatus>,
+    variables: HashMap<String, Value>,
 }
 
 impl Birdsong {
+    /// Returns the runner used by the methods that do not take an `Entity`.
+    pub fn main_runner(&self) -> Entity {
+        self.main_runner
+    }
+
     pub fn start(&mut self, script: String) {
-        self.script_data.script = script;
-        self.script_data.updated = true;
+        self.start_on(self.main_runner, script);
+    }
+
+    pub fn start_handle(&mut self, handle: Handle<BirdsongScript>) {
+        self.start_handle_on(self.main_runner, handle);
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_on(self.main_runner);
+    }
+
+    pub fn restart(&mut self) {
+        self.restart_on(self.main_runner);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running_on(self.main_runner)
     }
 
     pub fn get_curr_line(&mut self) -> usize {
-        return self.curr_line;
+        self.get_curr_line_on(self.main_runner)
+    }
+
+    pub fn complete_command(&mut self) {
+        self.complete_command_on(self.main_runner);
+    }
+
+    pub fn is_command_pending(&self) -> bool {
+        self.is_command_pending_on(self.main_runner)
+    }
+
+    pub fn start_on(&mut self, runner: Entity, script: String) {
+        self.push_request(runner, RunnerAction::Start(script));
+    }
+
+    pub fn start_handle_on(&mut self, runner: Entity, handle: Handle<BirdsongScript>) {
+        self.push_request(runner, RunnerAction::StartHandle(handle));
+    }
+
+    pub fn stop_on(&mut self, runner: Entity) {
+        self.push_request(runner, RunnerAction::Stop);
+    }
+
+    pub fn restart_on(&mut self, runner: Entity) {
+        self.push_request(runner, RunnerAction::Restart);
+    }
+
+    pub fn is_running_on(&self, runner: Entity) -> bool {
+        // Requests are applied on the next update, so the latest one wins over the last known status.
+        let requested = self.requests.iter().rev().find(|request| request.runner == runner && !matches!(request.action, RunnerAction::CompleteCommand));
+        match requested {
+            Some(request) => !matches!(request.action, RunnerAction::Stop),
+            None => self.statuses.get(&runner).is_some_and(|status| status.running),
+        }
+    }
+
+    pub fn get_curr_line_on(&self, runner: Entity) -> usize {
+        self.statuses.get(&runner).map_or(0, |status| status.curr_line)
+    }
+
+    pub fn complete_command_on(&mut self, runner: Entity) {
+        self.push_request(runner, RunnerAction::CompleteCommand);
+    }
+
+    pub fn is_command_pending_on(&self, runner: Entity) -> bool {
+        self.statuses.get(&runner).is_some_and(|status| status.command_pending)
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    pub fn set_var(&mut self, name: &str, value: impl Into<Value>) {
+        self.variables.insert(name.to_string(), value.into());
+    }
+
+    pub fn remove_var(&mut self, name: &str) -> Option<Value> {
+        self.variables.remove(name)
+    }
+
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.variables
+    }
+
+    fn push_request(&mut self, runner: Entity, action: RunnerAction) {
+        self.requests.push(RunnerRequest{runner, action, attempts: 0});
+    }
+}
+
+struct RunnerRequest {
+    runner: Entity,
+    action: RunnerAction,
+    attempts: u32,
+}
+
+enum RunnerAction {
+    Start(String),
+    StartHandle(Handle<BirdsongScript>),
+    Stop,
+    Restart,
+    CompleteCommand,
+}
+
+#[derive(Default)]
+struct RunnerStatus {
+    curr_line: usize,
+    running: bool,
+    command_pending: bool,
+}
+
+/// A dialogue running on its own entity, with its own script, settings and spawned children.
+#[derive(Component)]
+pub struct DialogueRunner {
+    /// Whether the runner reacts to the player's input.
+    pub accepts_input: bool,
+    /// Advances text lines automatically this many seconds after they are fully printed.
+    pub auto_advance: Option<f32>,
+    /// Picks and varies the voice blips. Set it to `BirdsongRng::new(seed)` for blips that repeat from run to run.
+    pub rng: BirdsongRng,
+    initialized: bool,
+    running: bool,
+    command_state: CommandState,
+    advance_timer: f32,
+    fast_forward: bool,
+    script_data: ScriptData,
+    // The plugin settings, restored when the runner is reset.
+    default_settings: SettingsData,
+    settings: SettingsData,
+    fonts: FontsData,
+    entries: EntriesData,
+    dbox: DialogueBoxData,
+    choices: ChoicesData,
+    actors: ActorsData,
+    backgrounds: BackgroundsData,
+    panels: PanelsData,
+    stage: StageData,
+}
+
+impl Default for DialogueRunner {
+    fn default() -> DialogueRunner {
+        DialogueRunner{accepts_input: true, auto_advance: None, rng: BirdsongRng::default(), initialized: false, running: false, command_state: CommandState::Idle, advance_timer: 0., fast_forward: false, script_data: ScriptData::new(), default_settings: SettingsData::default(), settings: SettingsData::default(), fonts: FontsData{font_map: HashMap::new()}, entries: EntriesData::new(), dbox: DialogueBoxData::new(), choices: ChoicesData::new(), actors: ActorsData::new(DEFAULT_VOICE_FREQUENCY), backgrounds: BackgroundsData::new(), panels: PanelsData::new(), stage: StageData::new()}
+    }
+}
+
+impl DialogueRunner {
+    fn init(&mut self, settings: &SettingsData) {
+        self.initialized = true;
+        self.default_settings = settings.clone();
+        self.settings = settings.clone();
+        self.actors = ActorsData::new(settings.voice_frequency);
+    }
+
+    fn is_transitioning(&self) -> bool {
+        self.backgrounds.layers.iter().any(|layer| layer.transition.is_some()) || self.stage.actors.iter().any(|actor| actor.transition.is_some()) || self.actors.transition.is_some()
+    }
+
+    fn is_on_command(&self) -> bool {
+        matches!(self.entries.list.get(self.dbox.entry_num), Some(Entry::Command{..}))
+    }
+
+    fn finish_transitions(&mut self) {
+        self.backgrounds.layers.iter_mut().filter_map(|layer| layer.transition.as_mut()).for_each(TransitionState::finish);
+        self.stage.actors.iter_mut().filter_map(|actor| actor.transition.as_mut()).for_each(TransitionState::finish);
+        self.actors.transition.iter_mut().for_each(TransitionState::finish);
+    }
+
+    fn spawned_entities(&self) -> Vec<Entity> {
+        let mut list: Vec<Entity> = self.choices.entities.clone();
+        list.extend(self.dbox.entity);
+        list.extend(self.actors.entity);
+        list.extend(self.actors.previous);
+        for layer in self.backgrounds.layers.iter() {
+            list.extend(layer.entity);
+            list.extend(layer.previous);
+        }
+        list.extend(self.panels.dbox.as_ref().map(|(ent, _)| *ent));
+        list.extend(self.panels.nameplate.as_ref().map(|(ent, _)| *ent));
+        list.extend(self.stage.actors.iter().filter_map(|actor| actor.entity));
+        list
+    }
+
+    fn reset(&mut self) {
+        self.settings = self.default_settings.clone();
+        self.fonts.font_map.clear();
+        self.entries = EntriesData::new();
+        self.dbox = DialogueBoxData::new();
+        self.choices = ChoicesData::new();
+        self.actors = ActorsData::new(self.settings.voice_frequency);
+        self.backgrounds = BackgroundsData::new();
+        self.panels = PanelsData::new();
+        self.stage = StageData::new();
+        self.command_state = CommandState::Idle;
+        self.advance_timer = 0.;
+        self.script_data.reset = false;
     }
 }
 
+#[derive(Bundle, Default)]
+pub struct DialogueRunnerBundle {
+    pub runner: DialogueRunner,
+    #[bundle]
+    pub spatial: SpatialBundle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CommandState {
+    Idle,
+    Waiting,
+    Completed,
+}
+
+#[derive(Clone, Default)]
 struct SettingsData {
     text_style: TextStyle,
     cursor_sprite: Handle<Image>,
     box_size: Vec2,
     box_position: Vec3,
     box_text_speed: f32,
+    box_fade_in: f32,
     voice_frequency: f32,
     choice_spacing: f32,
     choice_indent: f32,
     cursor_offset: f32,
     portrait_position:Vec3,
+    panel: Option<String>,
+    panel_padding: Vec2,
+    nameplate: Option<String>,
+    nameplate_offset: Vec3,
+    nameplate_size: Vec2,
+    stage_dim_color: Color,
+    portrait_transition: Option<Transition>,
 }
 
 struct ScriptData {
     script: String,
+    handle: Option<Handle<BirdsongScript>>,
+    loading: bool,
+    reloading: bool,
+    reset: bool,
     updated: bool,
 }
 
 struct EntriesData {
     updated: bool,
-    list: Vec<[String; 2]>,
+    finished: bool,
+    list: Vec<Entry>,
 }
 
 struct FontsData {
@@ -84,449 +362,1885 @@ struct FontsData {
 
 struct ChoicesData {
     enabled: bool,
-    created: bool,
+    entities: Vec<Entity>,
     updated: bool,
     curr_choice: i32,
-    next: i32,
     size: i32,
     cursor_sprite_map: HashMap<String, Handle<Image>>,
 }
 
 struct DialogueBoxData {
     enabled: bool,
-    created: bool,
+    entity: Option<Entity>,
     updated: bool,
     cursor: f32,
     entry: String,
+    runs: Vec<StyleRun>,
+    // Byte offset at which each grapheme of `entry` ends.
+    boundaries: Vec<usize>,
+    // Control tags of the entry, positioned by grapheme count.
+    controls: Vec<(usize, TypewriterControl)>,
+    next_control: usize,
+    pause: f32,
+    speed: Option<f32>,
+    instant: bool,
+    waiting: bool,
+    // Number of graphemes last written to the text entity.
+    written: Option<usize>,
+    // Time since the entry was set, and the time at which each revealed grapheme appeared.
+    clock: f32,
+    revealed_at: Vec<f32>,
+    glyphs: Vec<Entity>,
     entry_num: usize,
     is_printing: bool,
+    shown_entry: Option<usize>,
+    finished_entry: Option<usize>,
+    // Speed fitting the entry to its voice line, in place of the default one.
+    voice_speed: Option<f32>,
 }
 
 struct ActorsData {
     enabled: bool,
-    created: bool,
+    entity: Option<Entity>,
     updated: bool,
+    // Speaker and expression changes of the side portrait, with the portrait being replaced.
+    transition: Option<TransitionState>,
+    previous: Option<Entity>,
     portraits_map: HashMap<String, Handle<Image>>,
-    voices_map: HashMap<String, Handle<AudioSource>>,
+    expressions_map: HashMap<String, HashMap<String, Handle<Image>>>,
+    voices_map: HashMap<String, Vec<Handle<AudioSource>>>,
+    blips_map: HashMap<String, BlipDecl>,
+    // The entry and number of graphemes already considered for blips.
+    blipped: (usize, usize),
+    names_map: HashMap<String, String>,
     curr_name: String,
+    curr_expression: Option<String>,
     voice_timer: Timer,
+    voice_lines_map: HashMap<String, VoiceLineData>,
+    // Requested when a voiced entry is shown, then played by the actor system.
+    next_voice_line: Option<String>,
+    // The entry being voiced, with its sink. Blips are muted until the dialogue moves on.
+    voice_line: Option<(usize, Handle<AudioSink>)>,
+}
+
+struct VoiceLineData {
+    handle: Handle<AudioSource>,
+    duration: Option<f32>,
 }
 
 struct BackgroundsData {
+    map: HashMap<String, BackgroundImageData>,
+    layers: Vec<BackgroundLayerData>,
+}
+
+// Every background with the same z shares a layer.
+struct BackgroundLayerData {
+    z: f32,
     enabled: bool,
-    created: bool,
+    entity: Option<Entity>,
     updated: bool,
-    map: HashMap<String, BackgroundImageData>,
-    curr_name: String
+    curr_name: String,
+    transition: Option<TransitionState>,
+    // The replaced image, kept while the new one crossfades or slides in
+    previous: Option<Entity>,
+    // Hidden once its transition is over
+    clearing: bool,
+}
+
+struct TransitionState {
+    transition: Transition,
+    elapsed: f32,
 }
 
 struct BackgroundImageData {
     pos: Vec2,
     handle: Handle<Image>,
+    fit: BackgroundFit,
+    z: f32,
+    parallax: f32,
 }
 
-fn birdsong_setup_default_settings_system(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let font = asset_server.load(DEFAULT_FONT_PATH);
-    let text_style = TextStyle {
-        font,
-        font_size: DEFAULT_FONT_SIZE,
-        color: DEFAULT_TEXT_COLOR,
-    };    
-    let settings = SettingsData{text_style: text_style, cursor_sprite: asset_server.load(DEFAULT_CURSOR_PATH), box_size: DEFAULT_BOX_SIZE, box_position: DEFAULT_BOX_POSITION, box_text_speed: DEFAULT_TEXT_SPEED, voice_frequency: DEFAULT_VOICE_FREQUENCY, choice_spacing: DEFAULT_CHOICE_SPACING, choice_indent: DEFAULT_CHOICE_INDENT, cursor_offset: DEFAULT_CURSOR_OFFSET, portrait_position:DEFAULT_PORTRAIT_POSITION};
-    commands.insert_resource(settings);
+struct StageData {
+    actors: Vec<StageActorData>,
+    speaker: Option<String>,
 }
 
-fn birdsong_setup_system(mut commands: Commands, settings: ResMut<SettingsData>) {
-    let birdsong = Birdsong{script_data: ScriptData{script:"".to_string(), updated:true}, curr_line: 0};
-    commands.insert_resource(birdsong);
+struct StageActorData {
+    name: String,
+    expression: Option<String>,
+    position: Vec3,
+    entity: Option<Entity>,
+    // Cleared by exit entries, birdsong_update_stage_system then despawns the portrait.
+    present: bool,
+    transition: Option<TransitionState>,
+}
+
+struct PanelsData {
+    map: HashMap<String, PanelImageData>,
+    dbox: Option<(Entity, PanelLayout)>,
+    nameplate: Option<(Entity, PanelLayout)>,
+}
 
-    let fonts = FontsData{font_map: HashMap::new()};
-    commands.insert_resource(fonts);
+struct PanelImageData {
+    handle: Handle<Image>,
+    border: f32,
+    // Built once the image has loaded
+    atlas: Option<Handle<TextureAtlas>>,
+}
 
-    let entries = EntriesData{updated: false, list: Vec::new()};
-    commands.insert_resource(entries);
+// A spawned panel is respawned whenever its layout changes.
+#[derive(PartialEq)]
+struct PanelLayout {
+    name: String,
+    top_left: Vec3,
+    size: Vec2,
+    label: Option<String>,
+}
 
-    let dialoguebox = DialogueBoxData{enabled: false, created: false, updated: true,  cursor: 0.0, entry: "".to_string(), entry_num: 0, is_printing: false};
-    commands.insert_resource(dialoguebox);
+fn is_vowel(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(|c| c.to_lowercase().any(|c| VOWELS.contains(c)))
+}
 
-    let actors = ActorsData{enabled: false, created: false, updated: true, portraits_map: HashMap::new(), voices_map: HashMap::new(), curr_name:"none".to_string(), voice_timer: Timer::new(Duration::from_secs_f32(settings.voice_frequency), true)};
-    commands.insert_resource(actors);
+fn run_style(style: &RunStyle, base: &TextStyle, fonts: &FontsData) -> TextStyle {
+    TextStyle {
+        font: style.font.as_ref().and_then(|name| fonts.font_map.get(name)).cloned().unwrap_or_else(|| base.font.clone()),
+        font_size: style.size.unwrap_or(base.font_size),
+        color: style.color.unwrap_or(base.color),
+    }
+}
 
-    let choices = ChoicesData{enabled: false, created: false, updated: true, curr_choice: 0, next: 0, size: 0, cursor_sprite_map: HashMap::new()};
-    commands.insert_resource(choices);
+impl ScriptData {
+    fn new() -> ScriptData {
+        ScriptData{script:"".to_string(), handle: None, loading: false, reloading: false, reset: false, updated: false}
+    }
+}
 
-    let backgrounds = BackgroundsData{enabled: false, created: false, updated: true, map: HashMap::new(), curr_name:"none".to_string()};
-    commands.insert_resource(backgrounds);    
+impl EntriesData {
+    fn new() -> EntriesData {
+        EntriesData{updated: false, finished: false, list: Vec::new()}
+    }
 }
 
-fn birdsong_parse_script_system(asset_server: Res<AssetServer>, mut birdsong: ResMut<Birdsong>, mut fonts: ResMut<FontsData>, mut actors: ResMut<ActorsData>, mut backgrounds: ResMut<BackgroundsData>, mut entries: ResMut<EntriesData>, mut choices: ResMut<ChoicesData>) {
-    if birdsong.script_data.updated {
-        birdsong.script_data.updated = false;
-        let mut state = 0; // 0: Sprites, 1: Voices, 2: Lines
+impl DialogueBoxData {
+    fn new() -> DialogueBoxData {
+        DialogueBoxData{enabled: false, entity: None, updated: true,  cursor: 0.0, entry: "".to_string(), runs: Vec::new(), boundaries: Vec::new(), controls: Vec::new(), next_control: 0, pause: 0., speed: None, instant: false, waiting: false, written: None, clock: 0., revealed_at: Vec::new(), glyphs: Vec::new(), entry_num: 0, is_printing: false, shown_entry: None, finished_entry: None, voice_speed: None}
+    }
 
-        let script_str = birdsong.script_data.script.as_str();
-        for line in script_str.lines() {
-            match line {
-                "## FONTS" => {
-                    state = 0;
-                    continue;
-                },
-                "## CURSOR SPRITES" => {
-                    state = 1;
-                    continue;
-                },
-                "## BACKGROUNDS" => {
-                    state = 2;
-                    continue;
-                },
-                "## ACTORS" => {
-                    state = 3;
-                    continue;
-                },
-                "## ENTRIES" => {
-                    state = 4;
-                    continue;
-                },
-                "" => continue,
-                _ => (),
-            }
-            let line_vec: Vec<&str> = line.split("#").collect();
-            match state {
-                0 => {
-                    fonts.font_map.insert(line_vec[0].to_string(), asset_server.load(line_vec[1]));
-                },
-                1 => {
-                    choices.cursor_sprite_map.insert(line_vec[0].to_string(), asset_server.load(line_vec[1]));
-                },
-                2 => {
-                    let bg_vec: Vec<&str> = line_vec[1].split("@").collect();
-                    let bg_size_vec: Vec<&str> = bg_vec[1].split("x").collect();
-                    let bg_pos = Vec2::new(bg_size_vec[0].parse().unwrap(), bg_size_vec[1].parse().unwrap()); 
-                    let bg = asset_server.load(bg_vec[0]);
-                    backgrounds.map.insert(line_vec[0].to_string(), BackgroundImageData{pos:bg_pos, handle:bg});
-                },
-                3 => {
-                    let actor_vec: Vec<&str> = line_vec[1].split("|").collect();
-                    let sprite = asset_server.load(actor_vec[0]); 
-                    actors.portraits_map.insert(line_vec[0].to_string(), sprite);
-                    let voice = asset_server.load(actor_vec[1]); 
-                    actors.voices_map.insert(line_vec[0].to_string(), voice);
-                },
-                4 => {
-                    entries.list.push([line_vec[0].to_string(), line_vec[1].to_string()]);
-                },
-                _ => (),
-            }
-                
+    fn set_entry(&mut self, text: &str, runs: &[StyleRun], controls: &[ControlTag]) {
+        self.entry = text.to_string();
+        self.runs = runs.to_vec();
+        self.boundaries = text.grapheme_indices(true).map(|(index, grapheme)| index + grapheme.len()).collect();
+        self.controls = controls.iter().map(|tag| (self.boundaries.partition_point(|end| *end <= tag.at), tag.control)).collect();
+        self.next_control = 0;
+        self.pause = 0.;
+        self.speed = None;
+        self.instant = false;
+        self.waiting = false;
+        self.written = None;
+        self.clock = 0.;
+        self.revealed_at.clear();
+        self.voice_speed = None;
+    }
+
+    // Animated entries are laid out in full and drawn glyph by glyph.
+    fn is_animated(&self, fade_in: f32) -> bool {
+        fade_in > 0. || self.runs.iter().any(|run| run.style.shake || run.style.wave || run.style.rainbow)
+    }
+
+    fn length(&self) -> usize {
+        self.boundaries.len()
+    }
+
+    fn grapheme(&self, index: usize) -> &str {
+        let start = if index == 0 { 0 } else { self.boundaries[index - 1] };
+        &self.entry[start..self.boundaries[index]]
+    }
+
+    // Whether revealing a grapheme plays a voice blip. Spaces and punctuation never do.
+    fn blips_at(&self, mode: BlipMode, index: usize) -> bool {
+        match mode {
+            BlipMode::Timer => false,
+            BlipMode::Character => self.grapheme(index).chars().any(char::is_alphanumeric),
+            BlipMode::Syllable => is_vowel(self.grapheme(index)) && (index == 0 || !is_vowel(self.grapheme(index - 1))),
         }
-        entries.updated = true;
     }
-}
 
-fn birdsong_process_entry_system(mut settings: ResMut<SettingsData>, mut dbox: ResMut<DialogueBoxData>, fonts: Res<FontsData>, mut choices: ResMut<ChoicesData>, entries: Res<EntriesData>, mut actors: ResMut<ActorsData>,  mut backgrounds: ResMut<BackgroundsData>) {  
-    let entry_num = entries.list.len();
-    if entry_num > 0 && entries.updated {
-        let entry_type = entries.list[dbox.entry_num][0].clone();
-        if dbox.entry_num < entry_num {
-            match entry_type.as_str() {
-                "s" => {
-                    let settings_vec: Vec<&str> = entries.list[dbox.entry_num][1].split("|").collect();
-                    for setting in settings_vec {
-                        let setting_vec: Vec<&str> = setting.split(":").collect();
-                        match setting_vec[0] {
-                            "font" => {
-                                settings.text_style.font = fonts.font_map.get(setting_vec[1]).unwrap().clone();
-                            },
-                            "font_size" => {
-                                settings.text_style.font_size = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "font_color" => {
-                                let text_color_vec: Vec<&str> = setting_vec[1].split("x").collect();
-                                settings.text_style.color = Color::Rgba{red: text_color_vec[0].parse::<f32>().unwrap(), green: text_color_vec[1].parse::<f32>().unwrap(), blue: text_color_vec[2].parse::<f32>().unwrap(), alpha: text_color_vec[3].parse::<f32>().unwrap()}
-                            },
-                            "cursor" => {
-                                settings.cursor_sprite = choices.cursor_sprite_map.get(setting_vec[1]).unwrap().clone();
-                            },
-                            "box_size" => {
-                                let box_size_vec: Vec<&str> = setting_vec[1].split("x").collect();
-                                settings.box_size = Vec2::new(box_size_vec[0].parse::<f32>().unwrap(), box_size_vec[1].parse::<f32>().unwrap());
-                            },
-                            "box_position" => {
-                                let box_position_vec: Vec<&str> = setting_vec[1].split("x").collect();
-                                settings.box_position = Vec3::new(box_position_vec[0].parse::<f32>().unwrap(), box_position_vec[1].parse::<f32>().unwrap(), box_position_vec[2].parse::<f32>().unwrap());
-                            }
-                            "box_text_speed" => {
-                                settings.box_text_speed = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "voice_frequency" => {
-                                settings.voice_frequency = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "choice_spacing" => {
-                                settings.choice_spacing = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "choice_indent" => {
-                                settings.choice_indent = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "cursor_offset" => {
-                                settings.cursor_offset = setting_vec[1].parse::<f32>().unwrap();
-                            },
-                            "portrait_position" => {
-                                let portrait_position_vec: Vec<&str> = setting_vec[1].split("x").collect();
-                                settings.portrait_position = Vec3::new(portrait_position_vec[0].parse::<f32>().unwrap(), portrait_position_vec[1].parse::<f32>().unwrap(), portrait_position_vec[2].parse::<f32>().unwrap());
-                            },
-                            _ => {},
-                        }
-                    }
-                    dbox.entry_num += 1;
-                }
-                "c" => {
-                    if dbox.enabled {
-                        dbox.enabled = false;
-                    }
-                    if actors.enabled {
-                        actors.enabled = false;
-                    }
-                    if !choices.enabled {
-                        choices.enabled = true;
-                    }
-                    choices.updated = false;
-                },
-                "t" => {
-                    if !dbox.enabled {
-                        dbox.enabled = true;
-                    } 
-                    dbox.updated = false;
-                    
-                    let entry_vec: Vec<&str> = entries.list[dbox.entry_num][1].split("@").collect();
-                    match entry_vec.len() {
-                        1 => {
-                            dbox.entry = entry_vec[0].to_string();
-                        },
-                        2 => {
-                            actors.curr_name = entry_vec[0].to_string();
-                            actors.updated = false;
-                            if !actors.enabled {
-                                actors.enabled = true;
-                            }
-                            dbox.entry = entry_vec[1].to_string();
-                        },
-                        _ => {}
-                    }
-                },
-                "i" => {
-                    if !backgrounds.enabled {
-                        backgrounds.enabled = true;
+    // One section per style run, holding the part of the first `count` graphemes that falls in that run.
+    fn sections(&self, count: usize, base: &TextStyle, fonts: &FontsData) -> Vec<TextSection> {
+        let revealed = match count.min(self.length()) {
+            0 => 0,
+            count => self.boundaries[count - 1],
+        };
+        if self.runs.is_empty() {
+            return vec![TextSection{value: String::new(), style: base.clone()}];
+        }
+        let mut start = 0;
+        self.runs.iter().map(|run| {
+            let value = self.entry[start..run.end.min(revealed).max(start)].to_string();
+            start = run.end;
+            TextSection{value, style: run_style(&run.style, base, fonts)}
+        }).collect()
+    }
+
+    // Reveals `delta` seconds worth of text, applying the control tags it reaches.
+    fn advance(&mut self, mut delta: f32, default_speed: f32, scale: f32) {
+        while !self.waiting {
+            if let Some(&(at, control)) = self.controls.get(self.next_control) {
+                if at as f32 <= self.cursor {
+                    self.next_control += 1;
+                    match control {
+                        TypewriterControl::Pause(duration) => self.pause = duration,
+                        TypewriterControl::Speed(speed) => self.speed = speed,
+                        TypewriterControl::Wait => self.waiting = true,
+                        TypewriterControl::Instant(instant) => self.instant = instant,
                     }
-                    backgrounds.updated = false;
-                    backgrounds.curr_name = entries.list[dbox.entry_num][1].clone();
-                    dbox.entry_num += 1;
-                },
-                _ => {
-                    //actors.curr_name = "none".to_string();
+                    continue;
                 }
             }
+            if delta <= 0. {
+                break;
+            }
+            if self.pause > 0. {
+                let spent = (self.pause / scale).min(delta);
+                self.pause -= spent * scale;
+                delta -= spent;
+                continue;
+            }
+            let stop = self.controls.get(self.next_control).map_or(self.length(), |(at, _)| *at) as f32;
+            if self.cursor >= stop {
+                break;
+            }
+            let speed = self.speed.unwrap_or(default_speed) * scale;
+            if self.instant || (stop - self.cursor) / speed <= delta {
+                delta -= if self.instant { 0. } else { (stop - self.cursor) / speed };
+                self.cursor = stop;
+            } else {
+                self.cursor += speed * delta;
+                delta = 0.;
+            }
         }
     }
-}
 
-fn birdsong_handle_input_system(kb: Res<Input<KeyCode>>, mut dbox: ResMut<DialogueBoxData>, mut entries: ResMut<EntriesData>, mut choices: ResMut<ChoicesData>) {
-    if kb.just_pressed(KeyCode::Space) || kb.just_pressed(KeyCode::Return) {
-        if choices.enabled {
-            dbox.entry_num = choices.next as usize;
-            dbox.cursor = 0.;
-            choices.enabled = false;
+    // Pauses and waits still hold the typewriter even once every character is out.
+    fn is_done(&self) -> bool {
+        self.cursor >= self.length() as f32 && self.next_control >= self.controls.len() && self.pause <= 0. && !self.waiting
+    }
+
+    fn is_paused(&self) -> bool {
+        self.waiting || self.pause > 0.
+    }
+
+    // Resumes from a `[wait]`, otherwise prints everything up to the next one.
+    fn skip(&mut self) {
+        if self.waiting {
+            self.waiting = false;
+            return;
         }
-        else {
-            if dbox.entry_num < entries.list.len() {
-                if dbox.is_printing {
-                    dbox.cursor = entries.list[dbox.entry_num][1].len() as f32;
-                }
-                else if dbox.entry_num < entries.list.len()-1 {
-                    dbox.entry_num += 1;
-                    dbox.cursor = 0.;
-                }
-            }
-            else {
-                entries.updated = false;
+        self.pause = 0.;
+        while let Some(&(at, control)) = self.controls.get(self.next_control) {
+            self.next_control += 1;
+            match control {
+                TypewriterControl::Speed(speed) => self.speed = speed,
+                TypewriterControl::Instant(instant) => self.instant = instant,
+                TypewriterControl::Pause(_) => (),
+                TypewriterControl::Wait => {
+                    self.cursor = self.cursor.max(at as f32);
+                    self.waiting = true;
+                    return;
+                },
             }
         }
+        self.cursor = self.length() as f32;
     }
-    if kb.just_pressed(KeyCode::Up) || kb.just_pressed(KeyCode::Z) || kb.just_pressed(KeyCode::W) {
-        if choices.curr_choice > 0 {
-            choices.curr_choice -= 1;
-            choices.updated = false;
-        }
+}
+
+impl ActorsData {
+    fn new(voice_frequency: f32) -> ActorsData {
+        ActorsData{enabled: false, entity: None, updated: true, transition: None, previous: None, portraits_map: HashMap::new(), expressions_map: HashMap::new(), voices_map: HashMap::new(), blips_map: HashMap::new(), blipped: (0, 0), names_map: HashMap::new(), curr_name:"none".to_string(), curr_expression: None, voice_timer: Timer::new(Duration::from_secs_f32(voice_frequency), true), voice_lines_map: HashMap::new(), next_voice_line: None, voice_line: None}
     }
-    if kb.just_pressed(KeyCode::Down) || kb.just_pressed(KeyCode::S) {
-        if choices.curr_choice < choices.size - 1 {
-            choices.curr_choice += 1;
-            choices.updated = false;
+
+    fn stop_voice_line(&mut self, sinks: &Assets<AudioSink>) {
+        if let Some(sink) = self.voice_line.take().and_then(|(_, handle)| sinks.get(&handle)) {
+            sink.stop();
         }
     }
 
+    fn portrait(&self) -> Handle<Image> {
+        self.portrait_of(&self.curr_name, self.curr_expression.as_ref())
+    }
+
+    // The portrait of an expression, or the default one.
+    fn portrait_of(&self, name: &String, expression: Option<&String>) -> Handle<Image> {
+        expression
+            .and_then(|expression| self.expressions_map.get(name)?.get(expression))
+            .or_else(|| self.portraits_map.get(name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn display_name(&self) -> String {
+        self.names_map.get(&self.curr_name).cloned().unwrap_or_else(|| self.curr_name.clone())
+    }
 }
 
-fn birdsong_update_dialoguebox_system(mut commands: Commands, time: Res<Time>, settings: Res<SettingsData>, mut dbox: ResMut<DialogueBoxData>, mut query: Query<(Entity, &mut DialogueBoxComponent, &mut Text)>) {
-    if dbox.enabled && !dbox.created {
-        dbox.created = true;
-        commands.spawn_bundle(Text2dBundle {
-            text: Text::from_section("", settings.text_style.clone()),
-            text_2d_bounds: Text2dBounds {
-                size: settings.box_size,
-            },
-            transform: Transform::from_translation(settings.box_position),
-            ..default()
-        })
-        .insert(DialogueBoxComponent);
-    }
-    for(ent, _, mut text) in query.iter_mut() {
-        if !dbox.enabled && dbox.created {
-            dbox.created = false;
-            dbox.updated = true;
-            commands.entity(ent).despawn();
-        }
-        else if !dbox.updated {
-            let curr_cursor = dbox.cursor.floor() as usize;
-            if curr_cursor < dbox.entry.len() {
-                dbox.is_printing = true;
-                let curr_slice = &dbox.entry[..curr_cursor];
-                text.sections[0].value = curr_slice.to_string();
-                dbox.cursor+=settings.box_text_speed*time.delta_seconds();
-            } else {
-                dbox.is_printing = false;
-                text.sections[0].value = dbox.entry.clone();
-            }
-        }
+impl ChoicesData {
+    fn new() -> ChoicesData {
+        ChoicesData{enabled: false, entities: Vec::new(), updated: true, curr_choice: 0, size: 0, cursor_sprite_map: HashMap::new()}
     }
-    dbox.updated = true;
 }
 
-fn birdsong_update_choices_system(mut commands: Commands, entries: Res<EntriesData>, dbox: Res<DialogueBoxData>, settings: Res<SettingsData>, mut choices: ResMut<ChoicesData>, mut cursor_query: Query<(&ChoiceCursorComponent, &mut Visibility)>, mut entity_query: Query<(Entity, &ChoiceItemComponent)>) {
-    if choices.enabled && !choices.created {
-        choices.created = true;
-        let choices_list = entries.list[dbox.entry_num][1].split("|");
-        let mut curr_delta = 0.;
-        let mut count = 0;
-        choices.curr_choice = 0;
+impl BackgroundsData {
+    fn new() -> BackgroundsData {
+        BackgroundsData{map: HashMap::new(), layers: Vec::new()}
+    }
+}
 
-        for choice in choices_list {
-            let choice_vec: Vec<&str> = choice.split("@").collect();
-            let choice_pos = settings.box_position + Vec3::new(0., -curr_delta, 0.);
+impl BackgroundLayerData {
+    fn new(z: f32) -> BackgroundLayerData {
+        BackgroundLayerData{z, enabled: false, entity: None, updated: true, curr_name:"none".to_string(), transition: None, previous: None, clearing: false}
+    }
+}
 
-            commands.spawn_bundle(Text2dBundle {
-                text: Text::from_section(choice_vec[0], settings.text_style.clone()),
-                text_2d_bounds: Text2dBounds {
-                    size: settings.box_size,
-                },
-                transform: Transform::from_translation(settings.box_position + Vec3::new(settings.choice_indent, -curr_delta, 0.)),
-                ..default()
-            }
-            )
-            .insert(ChoiceItemComponent);
-            
-            commands.spawn_bundle(SpriteBundle {
-                texture: settings.cursor_sprite.clone(),
-                transform: Transform {
-                    translation: choice_pos + Vec3::new(0., -settings.cursor_offset, 0.),
-                    ..default()
-                },
-                visibility: Visibility { is_visible: false },
-                ..default()
-            })
-            .insert(ChoiceCursorComponent{num: count, next: choice_vec[1].to_string().parse::<i32>().unwrap(), anchor: choice_pos})
-            .insert(ChoiceItemComponent);
-            curr_delta += settings.choice_spacing;
-            choices.size += 1;
-            count += 1;
-        }
+impl TransitionState {
+    fn new(transition: Transition) -> TransitionState {
+        TransitionState{transition, elapsed: 0.}
     }
 
-    if !choices.enabled && choices.created {
-        for(ent, _) in entity_query.iter_mut() {
-            choices.created = false;
-            choices.updated = true;
-            commands.entity(ent).despawn();
+    fn progress(&self) -> f32 {
+        if self.transition.duration <= 0. {
+            return 1.;
         }
+        self.transition.easing.ease((self.elapsed / self.transition.duration).min(1.))
     }
-    else if !choices.updated {
-        for(cursor, mut vis) in cursor_query.iter_mut() {
-            if cursor.num == choices.curr_choice {
-                choices.next = cursor.next;
-                vis.is_visible = true;
-            }
-            else {
-                vis.is_visible = false;
-            }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.transition.duration
+    }
+
+    fn finish(&mut self) {
+        self.elapsed = self.transition.duration;
+    }
+
+    // Tint and offset of a sprite coming in, or going out when `incoming` is false.
+    fn look(&self, incoming: bool, width: f32) -> (Color, Vec3) {
+        let progress = if incoming { self.progress() } else { 1. - self.progress() };
+        match self.transition.kind {
+            TransitionKind::Crossfade => (Color::rgba(1., 1., 1., progress), Vec3::ZERO),
+            TransitionKind::Fade => {
+                let value = (2. * progress - 1.).max(0.);
+                (Color::rgba(value, value, value, (2. * progress).min(1.)), Vec3::ZERO)
+            },
+            TransitionKind::SlideLeft => (Color::WHITE, Vec3::new(-width * (1. - progress), 0., 0.)),
+            TransitionKind::SlideRight => (Color::WHITE, Vec3::new(width * (1. - progress), 0., 0.)),
         }
     }
-    choices.updated = true;
 }
 
-fn birdsong_update_actor_system(time: Res<Time>, mut commands: Commands, dbox: Res<DialogueBoxData>, audio: Res<Audio>, mut actors: ResMut<ActorsData>, settings: Res<SettingsData>, mut query: Query<(Entity, &mut Handle<Image>, &mut Transform, &PortraitComponent)>) {
-    if actors.enabled && !actors.created {
-        actors.created = true;
-        commands.spawn_bundle(SpriteBundle {
-            texture: actors.portraits_map.get(&actors.curr_name).unwrap().clone(),
-            transform: Transform {
-                translation: settings.portrait_position,
-                ..default()
-            },
-            ..default()
-        })
-        .insert(PortraitComponent)
-        .insert(VoiceComponent);
+impl StageData {
+    fn new() -> StageData {
+        StageData{actors: Vec::new(), speaker: None}
     }
-    for(ent, mut sprite, mut transform, _) in query.iter_mut() {
-        if !actors.enabled && actors.created {
-            actors.created = false;
-            commands.entity(ent).despawn();
-        }
-        else if !actors.updated {
-            *sprite = actors.portraits_map.get(&actors.curr_name).unwrap().clone();
-            transform.translation = settings.portrait_position;
-        }
+
+    fn contains(&self, name: &str) -> bool {
+        self.actors.iter().any(|actor| actor.present && actor.name == name)
     }
-    if actors.enabled {
-        if actors.voice_timer.duration().as_secs_f32() != settings.voice_frequency {
-            actors.voice_timer = Timer::new(Duration::from_secs_f32(settings.voice_frequency), true);
-        }
-        actors.voice_timer.tick(time.delta());
-        if actors.voice_timer.just_finished() && dbox.is_printing {
-            audio.play(actors.voices_map.get(&actors.curr_name).unwrap().clone());
+}
+
+fn slot_position(slot: &StageSlot) -> Vec3 {
+    match slot {
+        StageSlot::Left => STAGE_LEFT,
+        StageSlot::Center => STAGE_CENTER,
+        StageSlot::Right => STAGE_RIGHT,
+        StageSlot::Custom(position) => *position,
+    }
+}
+
+impl PanelsData {
+    fn new() -> PanelsData {
+        PanelsData{map: HashMap::new(), dbox: None, nameplate: None}
+    }
+}
+
+fn birdsong_setup_default_settings_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(DEFAULT_FONT_PATH);
+    let text_style = TextStyle {
+        font,
+        font_size: DEFAULT_FONT_SIZE,
+        color: DEFAULT_TEXT_COLOR,
+    };    
+    let settings = SettingsData{text_style, cursor_sprite: asset_server.load(DEFAULT_CURSOR_PATH), box_size: DEFAULT_BOX_SIZE, box_position: DEFAULT_BOX_POSITION, box_text_speed: DEFAULT_TEXT_SPEED, box_fade_in: DEFAULT_FADE_IN, voice_frequency: DEFAULT_VOICE_FREQUENCY, choice_spacing: DEFAULT_CHOICE_SPACING, choice_indent: DEFAULT_CHOICE_INDENT, cursor_offset: DEFAULT_CURSOR_OFFSET, portrait_position:DEFAULT_PORTRAIT_POSITION, panel: None, panel_padding: DEFAULT_PANEL_PADDING, nameplate: None, nameplate_offset: DEFAULT_NAMEPLATE_OFFSET, nameplate_size: DEFAULT_NAMEPLATE_SIZE, stage_dim_color: DEFAULT_STAGE_DIM_COLOR, portrait_transition: None};
+    commands.insert_resource(settings);
+}
+
+fn birdsong_setup_system(mut commands: Commands, settings: Res<SettingsData>) {
+    let mut runner = DialogueRunner::default();
+    runner.init(&settings);
+    let main_runner = commands.spawn_bundle(DialogueRunnerBundle{runner, ..default()}).id();
+
+    let birdsong = Birdsong{main_runner, requests: Vec::new(), statuses: HashMap::new(), variables: HashMap::new()};
+    commands.insert_resource(birdsong);
+}
+
+fn birdsong_init_runner_system(settings: Res<SettingsData>, mut runners: Query<&mut DialogueRunner, Added<DialogueRunner>>) {
+    for mut runner in runners.iter_mut() {
+        if !runner.initialized {
+            runner.init(&settings);
         }
     }
-    actors.updated = true;
 }
 
-fn birdsong_update_background_system(mut commands: Commands, mut backgrounds: ResMut<BackgroundsData>, mut query: Query<(Entity, &mut Handle<Image>, &mut Transform, &BackgroundComponent)>) {
-    if backgrounds.enabled && !backgrounds.created {
-        backgrounds.created = true;
-        let bg = backgrounds.map.get(&backgrounds.curr_name).unwrap();
-        println!("{}", bg.pos.x);
-        commands.spawn_bundle(SpriteBundle {
-            texture: bg.handle.clone(),
-            transform: Transform {
-                translation: Vec3::new(bg.pos.x, bg.pos.y, 0.),
-                ..default()
+fn birdsong_apply_requests_system(mut birdsong: ResMut<Birdsong>, mut runners: Query<&mut DialogueRunner>) {
+    let requests: Vec<RunnerRequest> = birdsong.requests.drain(..).collect();
+    for mut request in requests {
+        let mut runner = match runners.get_mut(request.runner) {
+            Ok(runner) => runner,
+            Err(_) => {
+                // A runner spawned this frame only shows up once its commands are applied.
+                request.attempts += 1;
+                if request.attempts < 2 {
+                    birdsong.requests.push(request);
+                } else {
+                    warn!("Birdsong has no dialogue runner on entity {:?}", request.runner);
+                }
+                continue;
+            }
+        };
+        let script_data = &mut runner.script_data;
+        match request.action {
+            RunnerAction::Start(script) => {
+                script_data.script = script;
+                script_data.handle = None;
+                script_data.loading = false;
+                script_data.reset = true;
+                script_data.updated = true;
+            },
+            RunnerAction::StartHandle(handle) => {
+                script_data.handle = Some(handle);
+                script_data.loading = true;
+                script_data.reset = true;
+                script_data.updated = false;
+            },
+            RunnerAction::Stop => {
+                script_data.handle = None;
+                script_data.loading = false;
+                script_data.updated = false;
+                script_data.reset = true;
+            },
+            RunnerAction::Restart => {
+                // A script asset is fetched again, as the cached text may be older than the asset or not loaded yet.
+                if script_data.handle.is_some() {
+                    script_data.loading = true;
+                    script_data.reset = true;
+                    script_data.updated = false;
+                }
+                else if !script_data.script.is_empty() {
+                    script_data.loading = false;
+                    script_data.reset = true;
+                    script_data.updated = true;
+                }
+            },
+            RunnerAction::CompleteCommand => {
+                if runner.command_state == CommandState::Waiting {
+                    runner.command_state = CommandState::Completed;
+                }
             },
-            ..default()
-        })
-        .insert(BackgroundComponent);
-    }
-    for(ent, mut sprite, mut transform, _) in query.iter_mut() {
-        if !backgrounds.enabled && backgrounds.created {
-            backgrounds.created = false;
-            commands.entity(ent).despawn();
         }
-        else if !backgrounds.updated {
-            let bg = backgrounds.map.get(&backgrounds.curr_name).unwrap();
-            *sprite = bg.handle.clone();
-            transform.translation = Vec3::new(bg.pos.x, bg.pos.y, 0.);
+    }
+}
+
+fn birdsong_load_script_asset_system(asset_server: Res<AssetServer>, mut runners: Query<&mut DialogueRunner>, scripts: Res<Assets<BirdsongScript>>) {
+    for mut runner in runners.iter_mut() {
+        if runner.script_data.loading {
+            let source = match &runner.script_data.handle {
+                Some(handle) => match scripts.get(handle) {
+                    Some(script) => script.source.clone(),
+                    // A missing or unreadable file would otherwise keep the runner loading forever.
+                    None if asset_server.get_load_state(handle) == LoadState::Failed => {
+                        let path = asset_server.get_handle_path(handle).map_or_else(|| "?".to_string(), |path| path.path().display().to_string());
+                        error!("Birdsong could not load script '{}'", path);
+                        runner.script_data.loading = false;
+                        continue;
+                    },
+                    None => continue,
+                },
+                None => continue,
+            };
+            runner.script_data.script = source;
+            runner.script_data.loading = false;
+            runner.script_data.updated = true;
         }
     }
-    backgrounds.updated = true;
 }
 
-fn birdsong_update_exposed_line_system(mut birdsong: ResMut<Birdsong>, dbox: Res<DialogueBoxData>) {
-    birdsong.curr_line = dbox.entry_num;
-}
\ No newline at end of file
+fn birdsong_reload_script_asset_system(mut runners: Query<&mut DialogueRunner>, scripts: Res<Assets<BirdsongScript>>, mut asset_events: EventReader<AssetEvent<BirdsongScript>>) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Modified{handle} = event {
+            let script = match scripts.get(handle) {
+                Some(script) => script,
+                None => continue,
+            };
+            for mut runner in runners.iter_mut() {
+                if !runner.running || runner.script_data.loading || runner.script_data.handle.as_ref() != Some(handle) {
+                    continue;
+                }
+                runner.script_data.script = script.source.clone();
+                runner.script_data.reloading = true;
+                runner.script_data.updated = true;
+            }
+        }
+    }
+}
+
+fn birdsong_parse_script_system(mut commands: Commands, asset_server: Res<AssetServer>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut diagnostics: EventWriter<BirdsongDiagnosticEvent>, mut started: EventWriter<DialogueStartedEvent>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let runner = &mut *runner;
+        // A new script waits for birdsong_teardown_system to clear the previous dialogue.
+        if !runner.script_data.updated || runner.script_data.reset {
+            continue;
+        }
+        runner.script_data.updated = false;
+        let reloading = runner.script_data.reloading;
+        runner.script_data.reloading = false;
+        let script = match parse_script(runner.script_data.script.as_str()) {
+            Ok(script) => script,
+            Err(err) => {
+                for error in err.errors {
+                    error!("Birdsong script error at {}", error);
+                    diagnostics.send(BirdsongDiagnosticEvent{runner: runner_entity, error});
+                }
+                continue;
+            }
+        };
+
+        // A reloaded script replaces the previous one, while the current line is kept (or clamped) below.
+        if reloading {
+            runner.fonts.font_map.clear();
+            runner.choices.cursor_sprite_map.clear();
+            runner.backgrounds.map.clear();
+            runner.panels.map.clear();
+            runner.actors.portraits_map.clear();
+            runner.actors.expressions_map.clear();
+            runner.actors.voices_map.clear();
+            runner.actors.blips_map.clear();
+            runner.actors.names_map.clear();
+            runner.actors.voice_lines_map.clear();
+            runner.entries.list.clear();
+        }
+
+        for (name, path) in script.fonts.iter() {
+            runner.fonts.font_map.insert(name.clone(), asset_server.load(path.as_str()));
+        }
+        for (name, path) in script.cursor_sprites.iter() {
+            runner.choices.cursor_sprite_map.insert(name.clone(), asset_server.load(path.as_str()));
+        }
+        for (name, bg) in script.backgrounds.iter() {
+            runner.backgrounds.map.insert(name.clone(), BackgroundImageData{pos: bg.pos, handle: asset_server.load(bg.path.as_str()), fit: bg.fit, z: bg.z, parallax: bg.parallax});
+        }
+        for (name, panel) in script.panels.iter() {
+            runner.panels.map.insert(name.clone(), PanelImageData{handle: asset_server.load(panel.path.as_str()), border: panel.border, atlas: None});
+        }
+        for (name, actor) in script.actors.iter() {
+            runner.actors.portraits_map.insert(name.clone(), asset_server.load(actor.portrait.as_str()));
+            runner.actors.voices_map.insert(name.clone(), actor.voices.iter().map(|voice| asset_server.load(voice.as_str())).collect());
+            runner.actors.names_map.insert(name.clone(), actor.name.clone());
+            runner.actors.expressions_map.insert(name.clone(), actor.expressions.iter().map(|(expression, path)| (expression.clone(), asset_server.load(path.as_str()))).collect());
+        }
+        runner.actors.blips_map.extend(script.blips);
+        for (name, voice_line) in script.voice_lines.iter() {
+            runner.actors.voice_lines_map.insert(name.clone(), VoiceLineData{handle: asset_server.load(voice_line.path.as_str()), duration: voice_line.duration});
+        }
+        runner.entries.list.extend(script.entries);
+        runner.entries.updated = true;
+
+        if reloading {
+            let dbox = &mut runner.dbox;
+            dbox.entry_num = dbox.entry_num.min(runner.entries.list.len().saturating_sub(1));
+            dbox.updated = false;
+            let backgrounds = &mut runner.backgrounds;
+            for layer in backgrounds.layers.iter_mut() {
+                if backgrounds.map.contains_key(&layer.curr_name) {
+                    layer.updated = false;
+                } else {
+                    layer.enabled = false;
+                }
+            }
+            let actors = &mut runner.actors;
+            if actors.portraits_map.contains_key(&actors.curr_name) {
+                actors.updated = false;
+            } else {
+                actors.enabled = false;
+            }
+            for ent in runner.choices.entities.drain(..) {
+                commands.entity(ent).despawn_recursive();
+            }
+            runner.choices.enabled = false;
+            runner.choices.size = 0;
+        } else if !runner.entries.list.is_empty() {
+            runner.running = true;
+            started.send(DialogueStartedEvent{runner: runner_entity});
+        } else {
+            warn!("Birdsong script has no entries, is its '## ENTRIES' header missing?");
+        }
+    }
+}
+
+fn birdsong_process_entry_system(mut birdsong: ResMut<Birdsong>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut line_shown: EventWriter<LineShownEvent>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        process_entry(runner_entity, &mut runner, &mut birdsong.variables, &mut line_shown);
+    }
+}
+
+fn process_entry(runner_entity: Entity, runner: &mut DialogueRunner, variables: &mut HashMap<String, Value>, line_shown: &mut EventWriter<LineShownEvent>) {
+    // Transitions hold the dialogue until they are over or skipped.
+    if runner.is_transitioning() {
+        return;
+    }
+    let DialogueRunner{settings, dbox, fonts, choices, entries, actors, backgrounds, stage, ..} = runner;
+    let entry_num = entries.list.len();
+    if entry_num > 0 && entries.updated && dbox.entry_num >= entry_num {
+        entries.updated = false;
+        entries.finished = true;
+    }
+    if entry_num > 0 && entries.updated && dbox.entry_num < entry_num {
+        let entry = &entries.list[dbox.entry_num];
+        if !matches!(entry, Entry::Text{..}) {
+            dbox.shown_entry = None;
+            dbox.finished_entry = None;
+        }
+        match entry {
+            Entry::Settings(settings_list) => {
+                for setting in settings_list {
+                    match setting {
+                        Setting::Font(name) => {
+                            if let Some(font) = fonts.font_map.get(name) {
+                                settings.text_style.font = font.clone();
+                            }
+                        },
+                        Setting::FontSize(size) => {
+                            settings.text_style.font_size = *size;
+                        },
+                        Setting::FontColor(color) => {
+                            settings.text_style.color = *color;
+                        },
+                        Setting::Cursor(name) => {
+                            if let Some(cursor) = choices.cursor_sprite_map.get(name) {
+                                settings.cursor_sprite = cursor.clone();
+                            }
+                        },
+                        Setting::BoxSize(size) => {
+                            settings.box_size = *size;
+                        },
+                        Setting::BoxPosition(position) => {
+                            settings.box_position = *position;
+                        },
+                        Setting::BoxTextSpeed(speed) => {
+                            settings.box_text_speed = *speed;
+                        },
+                        Setting::BoxFadeIn(duration) => {
+                            settings.box_fade_in = *duration;
+                        },
+                        Setting::Panel(name) => {
+                            settings.panel = Some(name.clone());
+                        },
+                        Setting::PanelPadding(padding) => {
+                            settings.panel_padding = *padding;
+                        },
+                        Setting::Nameplate(name) => {
+                            settings.nameplate = Some(name.clone());
+                        },
+                        Setting::NameplateOffset(offset) => {
+                            settings.nameplate_offset = *offset;
+                        },
+                        Setting::NameplateSize(size) => {
+                            settings.nameplate_size = *size;
+                        },
+                        Setting::StageDim(color) => {
+                            settings.stage_dim_color = *color;
+                        },
+                        Setting::PortraitTransition(transition) => {
+                            settings.portrait_transition = *transition;
+                        },
+                        Setting::VoiceFrequency(frequency) => {
+                            settings.voice_frequency = *frequency;
+                        },
+                        Setting::ChoiceSpacing(spacing) => {
+                            settings.choice_spacing = *spacing;
+                        },
+                        Setting::ChoiceIndent(indent) => {
+                            settings.choice_indent = *indent;
+                        },
+                        Setting::CursorOffset(offset) => {
+                            settings.cursor_offset = *offset;
+                        },
+                        Setting::PortraitPosition(position) => {
+                            settings.portrait_position = *position;
+                        },
+                    }
+                }
+                dbox.entry_num += 1;
+            }
+            Entry::Choice(items) => {
+                if !items.iter().any(|item| is_choice_available(&item.condition, variables)) {
+                    dbox.entry_num += 1;
+                    return;
+                }
+                if dbox.enabled {
+                    dbox.enabled = false;
+                }
+                if actors.enabled {
+                    actors.enabled = false;
+                }
+                if !choices.enabled {
+                    choices.enabled = true;
+                }
+                choices.updated = false;
+            },
+            Entry::Text{actor, expression, text, runs, controls, voice} => {
+                if !dbox.enabled {
+                    dbox.enabled = true;
+                } 
+                dbox.updated = false;
+                
+                if let Some(actor) = actor {
+                    // Actors on stage change through their own entries instead.
+                    let changed = !actors.enabled || actors.curr_name != *actor || actors.curr_expression != *expression;
+                    if changed && !stage.contains(actor) {
+                        actors.transition = settings.portrait_transition.map(TransitionState::new);
+                    }
+                    actors.curr_name = actor.clone();
+                    actors.curr_expression = expression.clone();
+                    actors.updated = false;
+                    if !actors.enabled {
+                        actors.enabled = true;
+                    }
+                    if let Some(stage_actor) = stage.actors.iter_mut().find(|stage_actor| stage_actor.name == *actor) {
+                        stage_actor.expression = expression.clone();
+                    }
+                }
+                // Narration has no speaker to show.
+                else if actors.enabled {
+                    actors.enabled = false;
+                }
+                stage.speaker = actor.clone();
+                if dbox.shown_entry != Some(dbox.entry_num) || dbox.entry != *text || dbox.runs != *runs {
+                    dbox.set_entry(text, runs, controls);
+                    let duration = voice.as_ref().and_then(|voice| actors.voice_lines_map.get(voice)?.duration);
+                    dbox.voice_speed = duration.filter(|duration| *duration > 0.).map(|duration| dbox.length() as f32 / duration);
+                }
+                if dbox.shown_entry != Some(dbox.entry_num) {
+                    dbox.shown_entry = Some(dbox.entry_num);
+                    actors.next_voice_line = voice.clone();
+                    line_shown.send(LineShownEvent{runner: runner_entity, entry: dbox.entry_num, actor: actor.clone(), text: text.clone()});
+                }
+            },
+            Entry::Image{name, transition} => {
+                if let Some(bg) = backgrounds.map.get(name) {
+                    let index = match backgrounds.layers.iter().position(|layer| layer.z == bg.z) {
+                        Some(index) => index,
+                        None => {
+                            backgrounds.layers.push(BackgroundLayerData::new(bg.z));
+                            backgrounds.layers.len() - 1
+                        },
+                    };
+                    let layer = &mut backgrounds.layers[index];
+                    if !layer.enabled {
+                        layer.enabled = true;
+                    }
+                    layer.updated = false;
+                    layer.curr_name = name.clone();
+                    layer.transition = transition.map(TransitionState::new);
+                    layer.clearing = false;
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::Enter{actor, expression, slot, transition} => {
+                match stage.actors.iter_mut().find(|stage_actor| stage_actor.name == *actor) {
+                    Some(stage_actor) => {
+                        stage_actor.expression = expression.clone();
+                        stage_actor.position = slot_position(slot);
+                        stage_actor.present = true;
+                        stage_actor.transition = transition.map(TransitionState::new);
+                    },
+                    None => stage.actors.push(StageActorData{name: actor.clone(), expression: expression.clone(), position: slot_position(slot), entity: None, present: true, transition: transition.map(TransitionState::new)}),
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::Exit{actor, transition} => {
+                if let Some(stage_actor) = stage.actors.iter_mut().find(|stage_actor| stage_actor.name == *actor) {
+                    stage_actor.present = false;
+                    stage_actor.transition = transition.map(TransitionState::new);
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::Hide{target: HideTarget::Background(only), transition} => {
+                // `None` clears every layer, `Some(None)` a background that no longer exists.
+                let z = match only {
+                    Some(BackgroundLayer::Named(name)) => Some(backgrounds.map.get(name).map(|bg| bg.z)),
+                    Some(BackgroundLayer::Z(z)) => Some(Some(*z)),
+                    None => None,
+                };
+                for layer in backgrounds.layers.iter_mut().filter(|layer| z.is_none_or(|z| z == Some(layer.z))) {
+                    match transition {
+                        Some(transition) => {
+                            layer.transition = Some(TransitionState::new(*transition));
+                            layer.clearing = true;
+                        },
+                        None => layer.enabled = false,
+                    }
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::Hide{target: HideTarget::Portrait, ..} => {
+                if actors.enabled {
+                    actors.enabled = false;
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::Label(_) => {
+                dbox.entry_num += 1;
+            },
+            Entry::Goto(target) => {
+                dbox.entry_num = *target;
+                dbox.cursor = 0.;
+            },
+            Entry::Set{name, value} => {
+                match value.eval(variables) {
+                    Ok(value) => {
+                        variables.insert(name.clone(), value);
+                    },
+                    Err(err) => warn!("Birdsong could not set '{}': {}", name, err),
+                }
+                dbox.entry_num += 1;
+            },
+            Entry::If{condition, next} => {
+                let jump = match condition.eval(variables) {
+                    Ok(value) => value.is_truthy(),
+                    Err(err) => {
+                        warn!("Birdsong could not evaluate condition: {}", err);
+                        false
+                    }
+                };
+                if jump {
+                    dbox.entry_num = *next;
+                    dbox.cursor = 0.;
+                } else {
+                    dbox.entry_num += 1;
+                }
+            },
+            Entry::Command{..} => {
+                // Handled by birdsong_run_command_system, which needs exclusive world access.
+            },
+        }
+    }
+}
+
+fn birdsong_run_command_system(world: &mut World) {
+    let mut pending = Vec::new();
+    let mut query = world.query::<(Entity, &mut DialogueRunner)>();
+    for (runner_entity, mut runner) in query.iter_mut(world) {
+        if !runner.entries.updated || runner.dbox.entry_num >= runner.entries.list.len() || runner.is_transitioning() {
+            continue;
+        }
+        let (name, args) = match &runner.entries.list[runner.dbox.entry_num] {
+            Entry::Command{name, args} => (name.clone(), args.clone()),
+            // A state left over from another entry must not leak into the next command.
+            _ => {
+                runner.command_state = CommandState::Idle;
+                continue;
+            },
+        };
+        match runner.command_state {
+            CommandState::Waiting => (),
+            CommandState::Completed => {
+                runner.command_state = CommandState::Idle;
+                runner.dbox.entry_num += 1;
+            },
+            CommandState::Idle => pending.push((runner_entity, name, args)),
+        }
+    }
+
+    for (runner_entity, name, args) in pending {
+        let handler = world.resource::<BirdsongCommands>().get(&name);
+        let status = match handler {
+            Some(handler) => handler(runner_entity, &args, world),
+            None => {
+                warn!("Birdsong has no handler registered for command '{}'", name);
+                CommandStatus::Done
+            }
+        };
+        // The handler may have despawned the runner.
+        if let Some(mut runner) = world.get_mut::<DialogueRunner>(runner_entity) {
+            match status {
+                CommandStatus::Done => runner.dbox.entry_num += 1,
+                CommandStatus::Blocking => runner.command_state = CommandState::Waiting,
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn birdsong_handle_input_system(time: Res<Time>, bindings: Res<BirdsongInput>, keys: Res<Input<KeyCode>>, mouse_buttons: Res<Input<MouseButton>>, gamepad_buttons: Res<Input<GamepadButton>>, gamepads: Res<Gamepads>, mut action_events: EventReader<BirdsongActionEvent>, cursors: Query<&ChoiceCursorComponent>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut choice_made: EventWriter<ChoiceMadeEvent>) {
+    let devices = InputDevices{keys: &keys, mouse_buttons: &mouse_buttons, gamepad_buttons: &gamepad_buttons, gamepads: &gamepads};
+    let pressed: Vec<BirdsongAction> = ALL_ACTIONS.iter().copied().filter(|action| bindings.just_pressed(*action, &devices)).collect();
+    let fast_forward = bindings.pressed(BirdsongAction::FastForward, &devices);
+    let fed: Vec<(BirdsongAction, Option<Entity>)> = action_events.iter().map(|event| (event.action, event.runner)).collect();
+
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let runner = &mut *runner;
+        let mut actions = if runner.accepts_input { pressed.clone() } else { Vec::new() };
+        for (action, target) in fed.iter() {
+            if target.map_or(runner.accepts_input, |target| target == runner_entity) {
+                actions.push(*action);
+            }
+        }
+        runner.fast_forward = (runner.accepts_input && fast_forward) || actions.contains(&BirdsongAction::FastForward);
+
+        let line_done = runner.dbox.enabled && !runner.dbox.is_printing && runner.dbox.finished_entry == Some(runner.dbox.entry_num);
+        if runner.fast_forward && (line_done || runner.dbox.waiting) {
+            actions.push(BirdsongAction::Advance);
+        }
+        if let Some(delay) = runner.auto_advance {
+            if line_done {
+                runner.advance_timer += time.delta_seconds();
+                if runner.advance_timer >= delay {
+                    actions.push(BirdsongAction::Advance);
+                }
+            } else {
+                runner.advance_timer = 0.;
+            }
+        }
+
+        for action in actions {
+            apply_action(runner_entity, runner, action, &cursors, &mut choice_made);
+        }
+    }
+}
+
+fn apply_action(runner_entity: Entity, runner: &mut DialogueRunner, action: BirdsongAction, cursors: &Query<&ChoiceCursorComponent>, choice_made: &mut EventWriter<ChoiceMadeEvent>) {
+    if runner.is_transitioning() && matches!(action, BirdsongAction::Advance | BirdsongAction::Skip) {
+        runner.finish_transitions();
+        return;
+    }
+    // Commands move on once their handler is done, not when the player advances.
+    if runner.is_on_command() && matches!(action, BirdsongAction::Advance | BirdsongAction::Skip) {
+        return;
+    }
+    match action {
+        BirdsongAction::Advance => {
+            runner.advance_timer = 0.;
+            if runner.choices.enabled {
+                confirm_choice(runner_entity, runner, cursors, choice_made);
+            }
+            else if runner.dbox.entry_num < runner.entries.list.len() {
+                if runner.dbox.is_printing {
+                    runner.dbox.skip();
+                }
+                else {
+                    runner.dbox.entry_num += 1;
+                    runner.dbox.cursor = 0.;
+                }
+            }
+            else if runner.entries.updated {
+                runner.entries.updated = false;
+                runner.entries.finished = true;
+            }
+        },
+        BirdsongAction::Skip => {
+            if runner.dbox.is_printing {
+                runner.dbox.skip();
+            }
+        },
+        BirdsongAction::Up => {
+            if runner.choices.curr_choice > 0 {
+                runner.choices.curr_choice -= 1;
+                runner.choices.updated = false;
+            }
+        },
+        BirdsongAction::Down => {
+            if runner.choices.curr_choice < runner.choices.size - 1 {
+                runner.choices.curr_choice += 1;
+                runner.choices.updated = false;
+            }
+        },
+        BirdsongAction::Confirm => {
+            if runner.choices.enabled {
+                confirm_choice(runner_entity, runner, cursors, choice_made);
+            }
+        },
+        BirdsongAction::Cancel => {
+            if runner.running {
+                runner.entries.updated = false;
+                runner.entries.finished = true;
+            }
+        },
+        BirdsongAction::FastForward => (),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn birdsong_handle_pointer_system(bindings: Res<BirdsongInput>, windows: Res<Windows>, mouse_buttons: Res<Input<MouseButton>>, touches: Res<Touches>, cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>, cursors: Query<&ChoiceCursorComponent>, texts: Query<(&GlobalTransform, &Text2dSize)>, mut runners: Query<(Entity, &GlobalTransform, &mut DialogueRunner)>, mut last_position: Local<Option<Vec2>>, mut choice_made: EventWriter<ChoiceMadeEvent>) {
+    if !bindings.pointer {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (camera_transform, projection) = match cameras.iter().find(|(camera, ..)| camera.is_active) {
+        Some((_, transform, projection)) => (transform, projection),
+        None => return,
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    let to_world = |position: Vec2| camera_transform.translation().truncate() + (position - window_size / 2.) * projection.scale;
+
+    let position = window.cursor_position().map(to_world);
+    let moved_to = position.filter(|position| Some(*position) != *last_position);
+    *last_position = position;
+    let mut presses = Vec::new();
+    // A left button bound to an action is already handled by the input system, and would act twice.
+    let bound = ALL_ACTIONS.iter().any(|action| bindings.mouse_buttons(*action).contains(&MouseButton::Left));
+    if let Some(position) = position {
+        if !bound && mouse_buttons.just_pressed(MouseButton::Left) {
+            presses.push(position);
+        }
+    }
+    for touch in touches.iter_just_pressed() {
+        let mut touch_position = touch.position();
+        // Touches only start from the bottom of the window on mobile targets, like the cursor does.
+        if !(cfg!(target_os = "android") || cfg!(target_os = "ios")) {
+            touch_position.y = window_size.y - touch_position.y;
+        }
+        presses.push(to_world(touch_position));
+    }
+
+    for (runner_entity, transform, mut runner) in runners.iter_mut() {
+        let runner = &mut *runner;
+        if !runner.accepts_input {
+            continue;
+        }
+        if runner.choices.enabled {
+            // Only the laid out text of a choice can be picked, not the empty rest of its row.
+            let choice_at = |point: Vec2| runner.choices.entities.iter()
+                .filter_map(|ent| cursors.get(*ent).ok())
+                .find(|cursor| texts.get(cursor.text).is_ok_and(|(text_transform, text_size)| contains_point(text_transform.translation().truncate(), text_size.size, point)))
+                .map(|cursor| cursor.num);
+            if let Some(position) = moved_to {
+                if let Some(num) = choice_at(position) {
+                    if num != runner.choices.curr_choice {
+                        runner.choices.curr_choice = num;
+                        runner.choices.updated = false;
+                    }
+                }
+            }
+            if let Some(num) = presses.iter().find_map(|press| choice_at(*press)) {
+                runner.choices.curr_choice = num;
+                confirm_choice(runner_entity, runner, &cursors, &mut choice_made);
+            }
+        }
+        else if runner.dbox.entity.is_some() {
+            let box_origin = transform.translation().truncate() + runner.settings.box_position.truncate();
+            if presses.iter().any(|press| contains_point(box_origin, runner.settings.box_size, *press)) {
+                apply_action(runner_entity, runner, BirdsongAction::Advance, &cursors, &mut choice_made);
+            }
+        }
+    }
+}
+
+// `top_left` and `point` are in world space, with y pointing up.
+fn contains_point(top_left: Vec2, size: Vec2, point: Vec2) -> bool {
+    point.x >= top_left.x && point.x <= top_left.x + size.x && point.y <= top_left.y && point.y >= top_left.y - size.y
+}
+
+// The target is read from the cursor of the highlighted choice, so a menu that is not spawned yet cannot be confirmed.
+fn confirm_choice(runner_entity: Entity, runner: &mut DialogueRunner, cursors: &Query<&ChoiceCursorComponent>, choice_made: &mut EventWriter<ChoiceMadeEvent>) {
+    let DialogueRunner{dbox, choices, ..} = runner;
+    let (item, next) = match choices.entities.iter().filter_map(|ent| cursors.get(*ent).ok()).find(|cursor| cursor.num == choices.curr_choice) {
+        Some(cursor) => (cursor.item as usize, cursor.next as usize),
+        None => return,
+    };
+    choice_made.send(ChoiceMadeEvent{runner: runner_entity, entry: dbox.entry_num, index: choices.curr_choice as usize, item, target: next});
+    dbox.entry_num = next;
+    dbox.cursor = 0.;
+    choices.enabled = false;
+}
+
+fn birdsong_update_dialoguebox_system(mut commands: Commands, time: Res<Time>, bindings: Res<BirdsongInput>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut query: Query<&mut Text, With<DialogueBoxComponent>>, mut finished: EventWriter<TypewriterFinishedEvent>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let scale = if runner.fast_forward { bindings.fast_forward_speed } else { 1. };
+        let DialogueRunner{settings, fonts, dbox, ..} = &mut *runner;
+        if dbox.enabled && dbox.entity.is_none() {
+            let ent = commands.spawn_bundle(Text2dBundle {
+                text: Text::from_section("", settings.text_style.clone()),
+                text_2d_bounds: Text2dBounds {
+                    size: settings.box_size,
+                },
+                transform: Transform::from_translation(settings.box_position),
+                ..default()
+            })
+            .insert(DialogueBoxComponent)
+            .id();
+            commands.entity(runner_entity).add_child(ent);
+            dbox.entity = Some(ent);
+            dbox.written = None;
+        }
+        if let Some(ent) = dbox.entity {
+            if !dbox.enabled {
+                dbox.entity = None;
+                dbox.updated = true;
+                dbox.glyphs.clear();
+                commands.entity(ent).despawn_recursive();
+            }
+            else if !dbox.updated {
+                if let Ok(mut text) = query.get_mut(ent) {
+                    let count = if dbox.is_done() { dbox.length() } else { (dbox.cursor.floor() as usize).min(dbox.length()) };
+                    let animated = dbox.is_animated(settings.box_fade_in);
+                    let written = if animated { dbox.length() } else { count };
+                    if dbox.written != Some(written) {
+                        text.sections = dbox.sections(written, &settings.text_style, fonts);
+                        if animated {
+                            for section in text.sections.iter_mut() {
+                                section.style.color.set_a(0.);
+                            }
+                        }
+                        dbox.written = Some(written);
+                    }
+                    let clock = dbox.clock;
+                    dbox.revealed_at.resize(count, clock);
+                    dbox.clock += time.delta_seconds();
+                    if !dbox.is_done() {
+                        dbox.is_printing = true;
+                        let speed = dbox.voice_speed.unwrap_or(settings.box_text_speed);
+                        dbox.advance(time.delta_seconds(), speed, scale);
+                    } else {
+                        dbox.is_printing = false;
+                        if dbox.finished_entry != Some(dbox.entry_num) {
+                            dbox.finished_entry = Some(dbox.entry_num);
+                            finished.send(TypewriterFinishedEvent{runner: runner_entity, entry: dbox.entry_num});
+                        }
+                    }
+                }
+            }
+        }
+        dbox.updated = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn birdsong_update_glyphs_system(mut commands: Commands, time: Res<Time>, windows: Res<Windows>, text_pipeline: Res<DefaultTextPipeline>, mut runners: Query<&mut DialogueRunner>, texts: Query<(&Text, &Text2dSize, ChangeTrackers<Text>), With<DialogueBoxComponent>>, mut glyph_query: Query<(&mut Transform, &mut TextureAtlasSprite, &mut Handle<TextureAtlas>), With<GlyphComponent>>) {
+    let scale_factor = windows.scale_factor(WindowId::primary()) as f32;
+    let seconds = time.seconds_since_startup() as f32;
+    for mut runner in runners.iter_mut() {
+        let DialogueRunner{settings, dbox, ..} = &mut *runner;
+        let ent = match dbox.entity {
+            Some(ent) => ent,
+            None => continue,
+        };
+        let mut shown: Vec<(Transform, TextureAtlasSprite, Handle<TextureAtlas>)> = Vec::new();
+        if let (Ok((text, size, tracker)), Some(layout)) = (texts.get(ent), text_pipeline.get_glyphs(&ent)) {
+            // The layout of a text changed this frame is only computed at the end of it.
+            if dbox.is_animated(settings.box_fade_in) && !tracker.is_changed() {
+                let alignment_offset = match text.alignment.vertical {
+                    VerticalAlign::Top => Vec2::new(0., -size.size.y),
+                    VerticalAlign::Center => Vec2::new(0., -size.size.y * 0.5),
+                    VerticalAlign::Bottom => Vec2::ZERO,
+                } + match text.alignment.horizontal {
+                    HorizontalAlign::Left => Vec2::ZERO,
+                    HorizontalAlign::Center => Vec2::new(-size.size.x * 0.5, 0.),
+                    HorizontalAlign::Right => Vec2::new(-size.size.x, 0.),
+                };
+                for glyph in layout.glyphs.iter() {
+                    let run = match dbox.runs.get(glyph.section_index) {
+                        Some(run) => run,
+                        None => continue,
+                    };
+                    let start = if glyph.section_index == 0 { 0 } else { dbox.runs[glyph.section_index - 1].end };
+                    let index = dbox.boundaries.partition_point(|end| *end <= start + glyph.byte_index);
+                    if index >= dbox.revealed_at.len() {
+                        continue;
+                    }
+                    let font_size = run.style.size.unwrap_or(settings.text_style.font_size);
+                    let mut offset = Vec2::ZERO;
+                    let mut color = run.style.color.unwrap_or(settings.text_style.color);
+                    if run.style.shake {
+                        let step = (seconds * SHAKE_RATE).floor();
+                        offset += Vec2::new(noise(step * 12.9898 + index as f32 * 78.233), noise(step * 39.346 + index as f32 * 11.135)) * font_size * SHAKE_AMPLITUDE;
+                    }
+                    if run.style.wave {
+                        offset.y += (seconds * WAVE_SPEED - index as f32 * WAVE_SPACING).sin() * font_size * WAVE_AMPLITUDE;
+                    }
+                    if run.style.rainbow {
+                        color = Color::hsla((seconds * RAINBOW_SPEED + index as f32 * RAINBOW_SPACING) % 360., 1., 0.6, color.a());
+                    }
+                    if settings.box_fade_in > 0. {
+                        color.set_a(color.a() * ((dbox.clock - dbox.revealed_at[index]) / settings.box_fade_in).min(1.));
+                    }
+                    let transform = Transform {
+                        translation: (alignment_offset + glyph.position / scale_factor + offset).extend(0.),
+                        scale: Vec3::splat(scale_factor.recip()),
+                        ..default()
+                    };
+                    let sprite = TextureAtlasSprite{color, ..TextureAtlasSprite::new(glyph.atlas_info.glyph_index)};
+                    shown.push((transform, sprite, glyph.atlas_info.texture_atlas.clone()));
+                }
+            }
+        }
+        let count = shown.len();
+        for (i, (transform, sprite, atlas)) in shown.into_iter().enumerate() {
+            if let Some(glyph_ent) = dbox.glyphs.get(i) {
+                if let Ok((mut glyph_transform, mut glyph_sprite, mut glyph_atlas)) = glyph_query.get_mut(*glyph_ent) {
+                    *glyph_transform = transform;
+                    *glyph_sprite = sprite;
+                    *glyph_atlas = atlas;
+                }
+            } else {
+                let glyph_ent = commands.spawn_bundle(SpriteSheetBundle {
+                    sprite,
+                    texture_atlas: atlas,
+                    transform,
+                    ..default()
+                })
+                .insert(GlyphComponent)
+                .id();
+                commands.entity(ent).add_child(glyph_ent);
+                dbox.glyphs.push(glyph_ent);
+            }
+        }
+        for glyph_ent in dbox.glyphs.drain(count.min(dbox.glyphs.len())..) {
+            commands.entity(glyph_ent).despawn_recursive();
+        }
+    }
+}
+
+// Pseudo-random value between -1 and 1.
+fn noise(x: f32) -> f32 {
+    (x.sin() * 43758.547).fract()
+}
+
+fn birdsong_update_choices_system(mut commands: Commands, birdsong: Res<Birdsong>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut cursor_query: Query<(&ChoiceCursorComponent, &mut Visibility)>, mut presented: EventWriter<ChoicePresentedEvent>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let DialogueRunner{settings, dbox, entries, choices, ..} = &mut *runner;
+        if choices.enabled && choices.entities.is_empty() {
+            let choices_list = match &entries.list[dbox.entry_num] {
+                Entry::Choice(items) => items,
+                _ => continue,
+            };
+            let mut curr_delta = 0.;
+            let mut options = Vec::new();
+            choices.curr_choice = 0;
+
+            for (count, (item, choice)) in choices_list.iter().enumerate().filter(|(_, choice)| is_choice_available(&choice.condition, &birdsong.variables)).enumerate() {
+                let choice_pos = settings.box_position + Vec3::new(0., -curr_delta, 0.);
+
+                let text_ent = commands.spawn_bundle(Text2dBundle {
+                    text: Text::from_section(choice.text.as_str(), settings.text_style.clone()),
+                    text_2d_bounds: Text2dBounds {
+                        size: settings.box_size,
+                    },
+                    transform: Transform::from_translation(settings.box_position + Vec3::new(settings.choice_indent, -curr_delta, 0.)),
+                    ..default()
+                }
+                )
+                .insert(ChoiceItemComponent)
+                .id();
+                
+                let cursor_ent = commands.spawn_bundle(SpriteBundle {
+                    texture: settings.cursor_sprite.clone(),
+                    transform: Transform {
+                        translation: choice_pos + Vec3::new(0., -settings.cursor_offset, 0.),
+                        ..default()
+                    },
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(ChoiceCursorComponent{num: count as i32, item: item as i32, next: choice.next as i32, text: text_ent})
+                .insert(ChoiceItemComponent)
+                .id();
+                commands.entity(runner_entity).push_children(&[text_ent, cursor_ent]);
+                choices.entities.push(text_ent);
+                choices.entities.push(cursor_ent);
+                curr_delta += settings.choice_spacing;
+                choices.size += 1;
+                options.push(choice.text.clone());
+            }
+            presented.send(ChoicePresentedEvent{runner: runner_entity, entry: dbox.entry_num, options});
+        }
+
+        if !choices.enabled && !choices.entities.is_empty() {
+            for ent in choices.entities.drain(..) {
+                commands.entity(ent).despawn_recursive();
+            }
+            choices.size = 0;
+            choices.curr_choice = 0;
+            choices.updated = true;
+        }
+        else if !choices.updated {
+            for ent in choices.entities.iter() {
+                if let Ok((cursor, mut vis)) = cursor_query.get_mut(*ent) {
+                    vis.is_visible = cursor.num == choices.curr_choice;
+                }
+            }
+        }
+        choices.updated = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn birdsong_update_actor_system(time: Res<Time>, mut commands: Commands, windows: Res<Windows>, audio: Res<Audio>, sinks: Res<Assets<AudioSink>>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut query: Query<(&mut Handle<Image>, &mut Sprite, &mut Transform), With<PortraitComponent>>) {
+    let width = windows.get_primary().map_or(0., |window| window.width());
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let DialogueRunner{settings, dbox, actors, stage, rng, ..} = &mut *runner;
+        if let Some(state) = &mut actors.transition {
+            state.elapsed += time.delta_seconds();
+        }
+        // Actors on stage speak through their stage portrait instead.
+        let shown = actors.enabled && !stage.contains(&actors.curr_name);
+        if !shown {
+            for ent in actors.entity.take().into_iter().chain(actors.previous.take()) {
+                commands.entity(ent).despawn_recursive();
+            }
+            actors.transition = None;
+        }
+        else {
+            let texture = actors.portrait();
+            let target = SpriteTarget{texture: texture.clone(), translation: settings.portrait_position, size: None, incoming: true, width, previous_depth: PREVIOUS_PORTRAIT_DEPTH};
+            update_transition_sprite(&mut commands, runner_entity, &mut query, &mut actors.entity, &mut actors.previous, &mut actors.transition, actors.updated, target, |commands, color, translation| spawn_portrait(commands, texture.clone(), color, translation));
+        }
+        // Voice lines play once, and stop when the dialogue moves on.
+        if actors.voice_line.as_ref().is_some_and(|(entry, _)| *entry != dbox.entry_num) {
+            actors.stop_voice_line(&sinks);
+        }
+        if let Some(name) = actors.next_voice_line.take() {
+            actors.stop_voice_line(&sinks);
+            if let Some(voice_line) = actors.voice_lines_map.get(&name) {
+                let sink = sinks.get_handle(audio.play(voice_line.handle.clone()));
+                actors.voice_line = Some((dbox.entry_num, sink));
+            }
+        }
+        if actors.enabled && actors.voice_line.is_none() {
+            let blip = actors.blips_map.get(&actors.curr_name).cloned().unwrap_or_default();
+            let play = match blip.mode {
+                BlipMode::Timer => {
+                    if actors.voice_timer.duration().as_secs_f32() != settings.voice_frequency {
+                        actors.voice_timer = Timer::new(Duration::from_secs_f32(settings.voice_frequency), true);
+                    }
+                    actors.voice_timer.tick(time.delta());
+                    actors.voice_timer.just_finished() && dbox.is_printing && !dbox.is_paused()
+                },
+                // At most one blip per frame, however many graphemes were revealed.
+                mode => {
+                    let revealed = (dbox.cursor as usize).min(dbox.length());
+                    let (entry, blipped) = actors.blipped;
+                    let from = if entry == dbox.entry_num { blipped.min(revealed) } else { 0 };
+                    actors.blipped = (dbox.entry_num, revealed);
+                    (from..revealed).any(|index| dbox.blips_at(mode, index))
+                },
+            };
+            let voices = actors.voices_map.get(&actors.curr_name).map_or(&[][..], |voices| voices.as_slice());
+            if play && !voices.is_empty() {
+                let voice = voices[rng.index(voices.len())].clone();
+                audio.play_with_settings(voice, PlaybackSettings{repeat: false, volume: rng.range(blip.volume.x, blip.volume.y), speed: rng.range(blip.pitch.x, blip.pitch.y)});
+            }
+        }
+        actors.updated = true;
+    }
+}
+
+fn birdsong_update_stage_system(mut commands: Commands, time: Res<Time>, windows: Res<Windows>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut query: Query<(&mut Handle<Image>, &mut Sprite, &mut Transform), With<StageActorComponent>>) {
+    let width = windows.get_primary().map_or(0., |window| window.width());
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let DialogueRunner{settings, actors, stage, ..} = &mut *runner;
+        for stage_actor in stage.actors.iter_mut() {
+            if let Some(state) = &mut stage_actor.transition {
+                state.elapsed += time.delta_seconds();
+            }
+            // Actors leaving with a transition stay until it is over.
+            if !stage_actor.present && stage_actor.transition.as_ref().is_none_or(|state| state.is_done()) {
+                if let Some(ent) = stage_actor.entity.take() {
+                    commands.entity(ent).despawn_recursive();
+                }
+                stage_actor.transition = None;
+                continue;
+            }
+            let texture = actors.portrait_of(&stage_actor.name, stage_actor.expression.as_ref());
+            let (tint, offset) = stage_actor.transition.as_ref().map_or((Color::WHITE, Vec3::ZERO), |state| state.look(stage_actor.present, width));
+            let color = if stage.speaker.as_ref() == Some(&stage_actor.name) { Color::WHITE } else { settings.stage_dim_color } * Vec4::from(tint);
+            match stage_actor.entity {
+                Some(ent) => {
+                    if let Ok((mut handle, mut sprite, mut transform)) = query.get_mut(ent) {
+                        *handle = texture;
+                        sprite.color = color;
+                        transform.translation = stage_actor.position + offset;
+                    }
+                },
+                None => {
+                    let ent = commands.spawn_bundle(SpriteBundle {
+                        sprite: Sprite{color, ..default()},
+                        texture,
+                        transform: Transform::from_translation(stage_actor.position + offset),
+                        ..default()
+                    })
+                    .insert(StageActorComponent)
+                    .id();
+                    commands.entity(runner_entity).add_child(ent);
+                    stage_actor.entity = Some(ent);
+                },
+            }
+            if stage_actor.transition.as_ref().is_some_and(|state| state.is_done()) && stage_actor.present {
+                stage_actor.transition = None;
+            }
+        }
+        stage.actors.retain(|stage_actor| stage_actor.present || stage_actor.entity.is_some());
+    }
+}
+
+fn birdsong_update_panels_system(mut commands: Commands, images: Res<Assets<Image>>, mut atlases: ResMut<Assets<TextureAtlas>>, mut runners: Query<(Entity, &mut DialogueRunner)>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let DialogueRunner{settings, dbox, actors, panels, ..} = &mut *runner;
+        for panel in panels.map.values_mut() {
+            if panel.atlas.is_none() {
+                if let Some(image) = images.get(&panel.handle) {
+                    panel.atlas = Some(atlases.add(nine_slice_atlas(panel.handle.clone(), image.size(), panel.border)));
+                }
+            }
+        }
+        // The box panel frames the text bounds, the nameplate is placed relative to the portrait.
+        let dbox_layout = settings.panel.as_ref().filter(|_| dbox.enabled).map(|name| PanelLayout {
+            name: name.clone(),
+            top_left: settings.box_position + Vec3::new(-settings.panel_padding.x, settings.panel_padding.y, -PANEL_DEPTH),
+            size: settings.box_size + settings.panel_padding * 2.,
+            label: None,
+        });
+        let nameplate_layout = settings.nameplate.as_ref().filter(|_| dbox.enabled && actors.enabled).map(|name| PanelLayout {
+            name: name.clone(),
+            top_left: settings.portrait_position + settings.nameplate_offset,
+            size: settings.nameplate_size,
+            label: Some(actors.display_name()),
+        });
+        update_panel(&mut commands, runner_entity, &panels.map, &mut panels.dbox, dbox_layout, &settings.text_style);
+        update_panel(&mut commands, runner_entity, &panels.map, &mut panels.nameplate, nameplate_layout, &settings.text_style);
+    }
+}
+
+fn update_panel(commands: &mut Commands, runner_entity: Entity, map: &HashMap<String, PanelImageData>, current: &mut Option<(Entity, PanelLayout)>, layout: Option<PanelLayout>, text_style: &TextStyle) {
+    if current.as_ref().map(|(_, current)| current) == layout.as_ref() {
+        return;
+    }
+    if let Some((ent, _)) = current.take() {
+        commands.entity(ent).despawn_recursive();
+    }
+    let layout = match layout {
+        Some(layout) => layout,
+        None => return,
+    };
+    // Tried again next frame until the image has loaded.
+    let (atlas, border) = match map.get(&layout.name) {
+        Some(PanelImageData{atlas: Some(atlas), border, ..}) => (atlas.clone(), *border),
+        _ => return,
+    };
+    let ent = spawn_nine_slice(commands, atlas, border, layout.top_left, layout.size);
+    commands.entity(ent).insert(PanelComponent);
+    if let Some(label) = &layout.label {
+        let text = commands.spawn_bundle(Text2dBundle {
+            text: Text::from_section(label.clone(), text_style.clone()).with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_xyz(layout.size.x / 2., -layout.size.y / 2., PANEL_DEPTH),
+            ..default()
+        })
+        .insert(NameplateComponent)
+        .id();
+        commands.entity(ent).add_child(text);
+    }
+    commands.entity(runner_entity).add_child(ent);
+    *current = Some((ent, layout));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn birdsong_update_background_system(mut commands: Commands, time: Res<Time>, windows: Res<Windows>, images: Res<Assets<Image>>, cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut query: Query<(&mut Handle<Image>, &mut Sprite, &mut Transform), With<BackgroundComponent>>) {
+    let (camera_position, scale) = cameras.iter().find(|(camera, ..)| camera.is_active).map_or((Vec2::ZERO, 1.), |(_, transform, projection)| (transform.translation().truncate(), projection.scale));
+    let view = windows.get_primary().map_or(Vec2::ZERO, |window| Vec2::new(window.width(), window.height()) * scale);
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        let backgrounds = &mut runner.backgrounds;
+        for layer in backgrounds.layers.iter_mut() {
+            if let Some(state) = &mut layer.transition {
+                state.elapsed += time.delta_seconds();
+            }
+            if layer.clearing && layer.transition.as_ref().is_none_or(|state| state.is_done()) {
+                layer.enabled = false;
+            }
+            if !layer.enabled {
+                for ent in layer.entity.take().into_iter().chain(layer.previous.take()) {
+                    commands.entity(ent).despawn_recursive();
+                }
+                continue;
+            }
+            let bg = match backgrounds.map.get(&layer.curr_name) {
+                Some(bg) => bg,
+                None => continue,
+            };
+            let translation = Vec3::new(bg.pos.x, bg.pos.y, layer.z) + (camera_position * bg.parallax).extend(0.);
+            let size = background_size(bg.fit, images.get(&bg.handle).map(|image| image.size()), view);
+            let target = SpriteTarget{texture: bg.handle.clone(), translation, size, incoming: !layer.clearing, width: view.x, previous_depth: PREVIOUS_BACKGROUND_DEPTH};
+            update_transition_sprite(&mut commands, runner_entity, &mut query, &mut layer.entity, &mut layer.previous, &mut layer.transition, layer.updated, target, |commands, color, translation| spawn_background(commands, bg, color, translation, size));
+            layer.updated = true;
+        }
+        backgrounds.layers.retain(|layer| layer.enabled);
+    }
+}
+
+// Where a sprite that changes image with a transition is drawn, before the transition moves it.
+struct SpriteTarget {
+    texture: Handle<Image>,
+    translation: Vec3,
+    size: Option<Vec2>,
+    // False while the sprite is leaving.
+    incoming: bool,
+    width: f32,
+    // How far the previous sprite is pushed back while the new one comes in over it.
+    previous_depth: f32,
+}
+
+// Spawns, swaps or moves a portrait or background sprite and runs its transition.
+// The new image comes in over the previous one, except when fading through black, where it replaces it once dark.
+#[allow(clippy::too_many_arguments)]
+fn update_transition_sprite<F: WorldQuery>(commands: &mut Commands, runner_entity: Entity, query: &mut Query<(&mut Handle<Image>, &mut Sprite, &mut Transform), F>, entity: &mut Option<Entity>, previous: &mut Option<Entity>, transition: &mut Option<TransitionState>, updated: bool, target: SpriteTarget, spawn: impl Fn(&mut Commands, Color, Vec3) -> Entity) {
+    let (color, offset) = transition.as_ref().map_or((Color::WHITE, Vec3::ZERO), |state| state.look(target.incoming, target.width));
+    let translation = target.translation + offset;
+    match *entity {
+        None => {
+            let ent = spawn(commands, color, translation);
+            commands.entity(runner_entity).add_child(ent);
+            *entity = Some(ent);
+        },
+        Some(ent) if !updated && transition.as_ref().is_some_and(|state| state.transition.kind != TransitionKind::Fade) => {
+            if let Ok((_, _, mut transform)) = query.get_mut(ent) {
+                transform.translation.z -= target.previous_depth;
+            }
+            if let Some(previous) = previous.replace(ent) {
+                commands.entity(previous).despawn_recursive();
+            }
+            let ent = spawn(commands, color, translation);
+            commands.entity(runner_entity).add_child(ent);
+            *entity = Some(ent);
+        },
+        Some(ent) => {
+            if let Ok((mut handle, mut sprite, mut transform)) = query.get_mut(ent) {
+                match transition.as_ref() {
+                    Some(state) if target.incoming && state.transition.kind == TransitionKind::Fade && state.progress() < 0.5 => {
+                        sprite.color = state.look(false, target.width).0;
+                    },
+                    _ => {
+                        if *handle != target.texture {
+                            *handle = target.texture;
+                        }
+                        sprite.color = color;
+                        sprite.custom_size = target.size;
+                        transform.translation = translation;
+                    },
+                }
+            }
+        },
+    }
+    if transition.as_ref().is_some_and(|state| state.is_done()) {
+        *transition = None;
+        if let Some(previous) = previous.take() {
+            commands.entity(previous).despawn_recursive();
+        }
+    }
+}
+
+// Size of a background in the given view, `None` keeping the image size.
+fn background_size(fit: BackgroundFit, image_size: Option<Vec2>, view: Vec2) -> Option<Vec2> {
+    match (fit, image_size) {
+        (BackgroundFit::Stretch, _) => Some(view),
+        (BackgroundFit::Cover, Some(size)) => Some(size * (view / size).max_element()),
+        (BackgroundFit::Contain, Some(size)) => Some(size * (view / size).min_element()),
+        _ => None,
+    }
+}
+
+fn spawn_portrait(commands: &mut Commands, texture: Handle<Image>, color: Color, translation: Vec3) -> Entity {
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite{color, ..default()},
+        texture,
+        transform: Transform::from_translation(translation),
+        ..default()
+    })
+    .insert(PortraitComponent)
+    .insert(VoiceComponent)
+    .id()
+}
+
+fn spawn_background(commands: &mut Commands, bg: &BackgroundImageData, color: Color, translation: Vec3, size: Option<Vec2>) -> Entity {
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite{color, custom_size: size, ..default()},
+        texture: bg.handle.clone(),
+        transform: Transform::from_translation(translation),
+        ..default()
+    })
+    .insert(BackgroundComponent)
+    .id()
+}
+
+fn birdsong_teardown_system(mut commands: Commands, sinks: Res<Assets<AudioSink>>, mut runners: Query<(Entity, &mut DialogueRunner)>, mut ended: EventWriter<DialogueEndedEvent>) {
+    for (runner_entity, mut runner) in runners.iter_mut() {
+        if runner.entries.finished || runner.script_data.reset {
+            runner.actors.stop_voice_line(&sinks);
+            for ent in runner.spawned_entities() {
+                commands.entity(ent).despawn_recursive();
+            }
+            runner.reset();
+            if runner.running {
+                runner.running = false;
+                ended.send(DialogueEndedEvent{runner: runner_entity});
+            }
+        }
+    }
+}
+
+fn is_choice_available(condition: &Option<Expr>, variables: &HashMap<String, Value>) -> bool {
+    match condition {
+        Some(condition) => match condition.eval(variables) {
+            Ok(value) => value.is_truthy(),
+            Err(err) => {
+                warn!("Birdsong could not evaluate choice condition: {}", err);
+                false
+            }
+        },
+        None => true,
+    }
+}
+
+fn birdsong_update_exposed_line_system(mut birdsong: ResMut<Birdsong>, runners: Query<(Entity, &DialogueRunner)>) {
+    birdsong.statuses.clear();
+    for (runner_entity, runner) in runners.iter() {
+        let running = runner.running || runner.script_data.loading || runner.script_data.updated;
+        birdsong.statuses.insert(runner_entity, RunnerStatus{curr_line: runner.dbox.entry_num, running, command_pending: runner.command_state == CommandState::Waiting});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Easing;
+
+    fn dbox(text: &str, controls: &[(usize, TypewriterControl)]) -> DialogueBoxData {
+        let mut dbox = DialogueBoxData::new();
+        let controls: Vec<ControlTag> = controls.iter().map(|(at, control)| ControlTag{at: *at, control: *control}).collect();
+        dbox.set_entry(text, &[StyleRun{end: text.len(), style: RunStyle::default()}], &controls);
+        dbox
+    }
+
+    fn assert_cursor(dbox: &DialogueBoxData, expected: f32) {
+        assert!((dbox.cursor - expected).abs() < 1e-4, "cursor is {}, expected {}", dbox.cursor, expected);
+    }
+
+    fn values(sections: Vec<TextSection>) -> Vec<String> {
+        sections.into_iter().map(|section| section.value).collect()
+    }
+
+    #[test]
+    fn restarts_a_script_asset_from_its_handle() {
+        let mut app = App::new();
+        let runner = app.world.spawn().insert(DialogueRunner::default()).id();
+        app.insert_resource(Birdsong{main_runner: runner, requests: Vec::new(), statuses: HashMap::new(), variables: HashMap::new()});
+        app.add_system(birdsong_apply_requests_system);
+        app.world.resource_mut::<Birdsong>().start_handle(Handle::default());
+        app.update();
+        {
+            let mut runner = app.world.get_mut::<DialogueRunner>(runner).unwrap();
+            assert!(runner.script_data.loading);
+            // The asset loads and its text starts running.
+            runner.script_data.script = "## ENTRIES\nt#Old text.".to_string();
+            runner.script_data.loading = false;
+            runner.script_data.reset = false;
+            runner.script_data.updated = true;
+        }
+        app.world.resource_mut::<Birdsong>().restart();
+        app.update();
+        let script_data = &app.world.get::<DialogueRunner>(runner).unwrap().script_data;
+        assert!(script_data.handle.is_some() && script_data.loading && script_data.reset && !script_data.updated);
+    }
+
+    #[test]
+    fn restarts_a_script_string_from_its_text() {
+        let mut app = App::new();
+        let runner = app.world.spawn().insert(DialogueRunner::default()).id();
+        app.insert_resource(Birdsong{main_runner: runner, requests: Vec::new(), statuses: HashMap::new(), variables: HashMap::new()});
+        app.add_system(birdsong_apply_requests_system);
+        app.world.resource_mut::<Birdsong>().start("## ENTRIES\nt#Hi.".to_string());
+        app.update();
+        app.world.get_mut::<DialogueRunner>(runner).unwrap().script_data.updated = false;
+        app.world.resource_mut::<Birdsong>().restart();
+        app.update();
+        let script_data = &app.world.get::<DialogueRunner>(runner).unwrap().script_data;
+        assert!(!script_data.loading && script_data.reset && script_data.updated);
+    }
+
+    #[test]
+    fn counts_graphemes_not_bytes() {
+        let mut dbox = dbox("ne\u{301}st", &[]);
+        assert_eq!(dbox.length(), 4);
+        let fonts = FontsData{font_map: HashMap::new()};
+        assert_eq!(values(dbox.sections(2, &TextStyle::default(), &fonts)), vec!["ne\u{301}"]);
+        assert_eq!(values(dbox.sections(9, &TextStyle::default(), &fonts)), vec!["ne\u{301}st"]);
+        dbox.skip();
+        assert_eq!(dbox.cursor, 4.);
+    }
+
+    #[test]
+    fn reveals_text_run_by_run() {
+        let mut dbox = DialogueBoxData::new();
+        let red = RunStyle{color: Some(Color::RED), ..RunStyle::default()};
+        dbox.set_entry("abcdef", &[StyleRun{end: 2, style: RunStyle::default()}, StyleRun{end: 4, style: red}, StyleRun{end: 6, style: RunStyle::default()}], &[]);
+        let fonts = FontsData{font_map: HashMap::new()};
+        let sections = dbox.sections(3, &TextStyle::default(), &fonts);
+        assert_eq!(sections[1].style.color, Color::RED);
+        assert_eq!(values(sections), vec!["ab", "c", ""]);
+        assert_eq!(values(dbox.sections(0, &TextStyle::default(), &fonts)), vec!["", "", ""]);
+    }
+
+    #[test]
+    fn reveals_graphemes_at_the_given_speed() {
+        let mut dbox = dbox("ne\u{301}st", &[]);
+        assert_eq!(dbox.length(), 4);
+        dbox.advance(0.5, 4., 1.);
+        assert_cursor(&dbox, 2.);
+        dbox.advance(0.25, 2., 2.);
+        assert_cursor(&dbox, 3.);
+        dbox.advance(10., 4., 1.);
+        assert_cursor(&dbox, 4.);
+        assert!(dbox.is_done());
+    }
+
+    #[test]
+    fn pauses_then_carries_on() {
+        let mut dbox = dbox("abcd", &[(2, TypewriterControl::Pause(1.))]);
+        dbox.advance(0.2, 10., 1.);
+        assert_cursor(&dbox, 2.);
+        dbox.advance(0.5, 10., 1.);
+        assert_cursor(&dbox, 2.);
+        assert!(dbox.is_paused());
+        dbox.advance(0.6, 10., 1.);
+        assert_cursor(&dbox, 3.);
+        assert!(!dbox.is_paused());
+    }
+
+    #[test]
+    fn changes_speed_until_closed() {
+        let mut dbox = dbox("abcdef", &[(1, TypewriterControl::Speed(Some(10.))), (3, TypewriterControl::Speed(None))]);
+        dbox.advance(1., 1., 1.);
+        assert_cursor(&dbox, 1.);
+        dbox.advance(0.2, 1., 1.);
+        assert_cursor(&dbox, 3.);
+        dbox.advance(1., 1., 1.);
+        assert_cursor(&dbox, 4.);
+    }
+
+    #[test]
+    fn waits_for_the_player() {
+        let mut dbox = dbox("abcd", &[(2, TypewriterControl::Wait)]);
+        dbox.advance(10., 10., 1.);
+        assert_cursor(&dbox, 2.);
+        assert!(dbox.waiting && dbox.is_paused() && !dbox.is_done());
+        dbox.advance(10., 10., 1.);
+        assert_cursor(&dbox, 2.);
+        dbox.skip();
+        assert!(!dbox.waiting);
+        dbox.advance(10., 10., 1.);
+        assert!(dbox.is_done());
+    }
+
+    #[test]
+    fn prints_instant_text_at_once() {
+        let mut dbox = dbox("abcde", &[(1, TypewriterControl::Instant(true)), (4, TypewriterControl::Instant(false))]);
+        dbox.advance(1., 1., 1.);
+        assert_cursor(&dbox, 1.);
+        dbox.advance(0.5, 1., 1.);
+        assert_cursor(&dbox, 4.5);
+        assert!(!dbox.instant);
+    }
+
+    fn blips(text: &str, mode: BlipMode) -> Vec<usize> {
+        let dbox = dbox(text, &[]);
+        (0..dbox.length()).filter(|index| dbox.blips_at(mode, *index)).collect()
+    }
+
+    #[test]
+    fn blips_on_letters_and_digits() {
+        assert_eq!(blips("Hi, 2 you!", BlipMode::Character), vec![0, 1, 4, 6, 7, 8]);
+        assert_eq!(blips("... ?!", BlipMode::Character), Vec::<usize>::new());
+        assert_eq!(blips("Hi", BlipMode::Timer), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn blips_on_vowel_groups() {
+        assert_eq!(blips("Beautiful day", BlipMode::Syllable), vec![1, 5, 7, 11]);
+        assert_eq!(blips("Été, oui", BlipMode::Syllable), vec![0, 2, 5]);
+        assert_eq!(blips("Hmm... !", BlipMode::Syllable), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn skips_to_the_next_wait() {
+        let mut dbox = dbox("abcdef", &[(1, TypewriterControl::Pause(5.)), (3, TypewriterControl::Wait), (5, TypewriterControl::Speed(Some(2.)))]);
+        dbox.skip();
+        assert_cursor(&dbox, 3.);
+        assert!(dbox.waiting && dbox.pause <= 0.);
+        dbox.skip();
+        assert!(!dbox.waiting);
+        dbox.skip();
+        assert_cursor(&dbox, 6.);
+        assert_eq!(dbox.speed, Some(2.));
+        assert!(dbox.is_done());
+    }
+
+    fn state(kind: TransitionKind, duration: f32, easing: Easing, elapsed: f32) -> TransitionState {
+        TransitionState{transition: Transition{kind, duration, easing}, elapsed}
+    }
+
+    #[test]
+    fn clamps_transition_progress() {
+        assert_eq!(state(TransitionKind::Fade, 2., Easing::Linear, 0.).progress(), 0.);
+        assert_eq!(state(TransitionKind::Fade, 2., Easing::Linear, 1.).progress(), 0.5);
+        assert_eq!(state(TransitionKind::Fade, 2., Easing::EaseIn, 1.).progress(), 0.25);
+        assert_eq!(state(TransitionKind::Fade, 2., Easing::Linear, 2.).progress(), 1.);
+        assert_eq!(state(TransitionKind::Fade, 2., Easing::EaseOut, 10.).progress(), 1.);
+        assert!(!state(TransitionKind::Fade, 2., Easing::Linear, 1.).is_done());
+        let mut finished = state(TransitionKind::Fade, 2., Easing::Linear, 0.);
+        finished.finish();
+        assert!(finished.is_done() && finished.progress() == 1.);
+    }
+
+    #[test]
+    fn ends_zero_duration_transitions_at_once() {
+        let instant = state(TransitionKind::Crossfade, 0., Easing::EaseInOut, 0.);
+        assert_eq!(instant.progress(), 1.);
+        assert!(instant.is_done());
+        assert_eq!(instant.look(true, 100.), (Color::rgba(1., 1., 1., 1.), Vec3::ZERO));
+    }
+
+    #[test]
+    fn looks_at_both_ends_of_a_transition() {
+        let looks = |kind: TransitionKind, elapsed: f32, incoming: bool| state(kind, 1., Easing::Linear, elapsed).look(incoming, 100.);
+        assert_eq!(looks(TransitionKind::Crossfade, 0., true), (Color::rgba(1., 1., 1., 0.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::Crossfade, 1., true), (Color::rgba(1., 1., 1., 1.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::Crossfade, 1., false), (Color::rgba(1., 1., 1., 0.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::Fade, 0., true), (Color::rgba(0., 0., 0., 0.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::Fade, 0.5, true), (Color::rgba(0., 0., 0., 1.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::Fade, 1., true), (Color::rgba(1., 1., 1., 1.), Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::SlideLeft, 0., true), (Color::WHITE, Vec3::new(-100., 0., 0.)));
+        assert_eq!(looks(TransitionKind::SlideLeft, 1., true), (Color::WHITE, Vec3::ZERO));
+        assert_eq!(looks(TransitionKind::SlideRight, 0., true), (Color::WHITE, Vec3::new(100., 0., 0.)));
+        assert_eq!(looks(TransitionKind::SlideRight, 1., false), (Color::WHITE, Vec3::new(100., 0., 0.)));
+    }
+
+    #[test]
+    fn fits_backgrounds_to_the_view() {
+        let view = Vec2::new(200., 200.);
+        let image = Some(Vec2::new(100., 50.));
+        assert_eq!(background_size(BackgroundFit::Native, image, view), None);
+        assert_eq!(background_size(BackgroundFit::Stretch, image, view), Some(view));
+        assert_eq!(background_size(BackgroundFit::Stretch, None, view), Some(view));
+        assert_eq!(background_size(BackgroundFit::Cover, image, view), Some(Vec2::new(400., 200.)));
+        assert_eq!(background_size(BackgroundFit::Contain, image, view), Some(Vec2::new(200., 100.)));
+        assert_eq!(background_size(BackgroundFit::Cover, None, view), None);
+    }
+}
@@ -0,0 +1,35 @@
+use bevy::prelude::{App, Entity, World};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    Done,
+    // The dialogue waits until `Birdsong::complete_command_on` is called for the runner.
+    Blocking,
+}
+
+pub type CommandHandler = Arc<dyn Fn(Entity, &[String], &mut World) -> CommandStatus + Send + Sync>;
+
+#[derive(Default)]
+pub struct BirdsongCommands {
+    handlers: HashMap<String, CommandHandler>,
+}
+
+impl BirdsongCommands {
+    pub fn get(&self, name: &str) -> Option<CommandHandler> {
+        self.handlers.get(name).cloned()
+    }
+}
+
+pub trait BirdsongAppExt {
+    /// Registers a handler run when the script reaches a `cmd#name|arg|...` entry. It receives the runner entity and the arguments.
+    fn add_birdsong_command<F>(&mut self, name: &str, handler: F) -> &mut Self where F: Fn(Entity, &[String], &mut World) -> CommandStatus + Send + Sync + 'static;
+}
+
+impl BirdsongAppExt for App {
+    fn add_birdsong_command<F>(&mut self, name: &str, handler: F) -> &mut Self where F: Fn(Entity, &[String], &mut World) -> CommandStatus + Send + Sync + 'static {
+        self.world.get_resource_or_insert_with(BirdsongCommands::default).handlers.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+}
@@ -1,12 +1,23 @@
-use bevy::prelude::Component;
-use bevy::math::Vec3;
+use bevy::prelude::{Component, Entity};
 
 #[derive(Component)]
 pub struct DialogueBoxComponent;
 
+#[derive(Component)]
+pub struct GlyphComponent;
+
+#[derive(Component)]
+pub struct PanelComponent;
+
+#[derive(Component)]
+pub struct NameplateComponent;
+
 #[derive(Component)]
 pub struct PortraitComponent;
 
+#[derive(Component)]
+pub struct StageActorComponent;
+
 #[derive(Component)]
 pub struct VoiceComponent;
 
@@ -16,8 +27,9 @@ pub struct BackgroundComponent;
 #[derive(Component)]
 pub struct ChoiceCursorComponent{
     pub num: i32,
+    pub item: i32,
     pub next: i32,
-    pub anchor: Vec3,
+    pub text: Entity,
 }
 
 #[derive(Component)]
@@ -0,0 +1,134 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BirdsongError {
+    MissingSeparator { line: usize, column: usize, token: String, separator: char },
+    UnexpectedSeparator { line: usize, column: usize, token: String, separator: char },
+    InvalidNumber { line: usize, column: usize, token: String },
+    InvalidVector { line: usize, column: usize, token: String, expected: usize },
+    UnknownEntryType { line: usize, column: usize, token: String },
+    UnknownSetting { line: usize, column: usize, token: String },
+    UnknownReference { line: usize, column: usize, token: String, kind: &'static str },
+    InvalidChoiceTarget { line: usize, column: usize, token: String },
+    DuplicateLabel { line: usize, column: usize, token: String },
+    InvalidExpression { line: usize, column: usize, token: String, reason: String },
+    InvalidCommand { line: usize, column: usize, token: String },
+    UnknownTag { line: usize, column: usize, token: String },
+    UnmatchedTag { line: usize, column: usize, token: String },
+    UnknownTransition { line: usize, column: usize, token: String },
+    UnknownEasing { line: usize, column: usize, token: String },
+    UnknownFitMode { line: usize, column: usize, token: String },
+    UnknownBlipMode { line: usize, column: usize, token: String },
+}
+
+impl BirdsongError {
+    pub fn line(&self) -> usize {
+        match self {
+            BirdsongError::MissingSeparator{line, ..} |
+            BirdsongError::UnexpectedSeparator{line, ..} |
+            BirdsongError::InvalidNumber{line, ..} |
+            BirdsongError::InvalidVector{line, ..} |
+            BirdsongError::UnknownEntryType{line, ..} |
+            BirdsongError::UnknownSetting{line, ..} |
+            BirdsongError::UnknownReference{line, ..} |
+            BirdsongError::InvalidChoiceTarget{line, ..} |
+            BirdsongError::DuplicateLabel{line, ..} |
+            BirdsongError::InvalidExpression{line, ..} |
+            BirdsongError::InvalidCommand{line, ..} |
+            BirdsongError::UnknownTag{line, ..} |
+            BirdsongError::UnmatchedTag{line, ..} |
+            BirdsongError::UnknownTransition{line, ..} |
+            BirdsongError::UnknownEasing{line, ..} |
+            BirdsongError::UnknownFitMode{line, ..} |
+            BirdsongError::UnknownBlipMode{line, ..} => *line,
+        }
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            BirdsongError::MissingSeparator{column, ..} |
+            BirdsongError::UnexpectedSeparator{column, ..} |
+            BirdsongError::InvalidNumber{column, ..} |
+            BirdsongError::InvalidVector{column, ..} |
+            BirdsongError::UnknownEntryType{column, ..} |
+            BirdsongError::UnknownSetting{column, ..} |
+            BirdsongError::UnknownReference{column, ..} |
+            BirdsongError::InvalidChoiceTarget{column, ..} |
+            BirdsongError::DuplicateLabel{column, ..} |
+            BirdsongError::InvalidExpression{column, ..} |
+            BirdsongError::InvalidCommand{column, ..} |
+            BirdsongError::UnknownTag{column, ..} |
+            BirdsongError::UnmatchedTag{column, ..} |
+            BirdsongError::UnknownTransition{column, ..} |
+            BirdsongError::UnknownEasing{column, ..} |
+            BirdsongError::UnknownFitMode{column, ..} |
+            BirdsongError::UnknownBlipMode{column, ..} => *column,
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        match self {
+            BirdsongError::MissingSeparator{token, ..} |
+            BirdsongError::UnexpectedSeparator{token, ..} |
+            BirdsongError::InvalidNumber{token, ..} |
+            BirdsongError::InvalidVector{token, ..} |
+            BirdsongError::UnknownEntryType{token, ..} |
+            BirdsongError::UnknownSetting{token, ..} |
+            BirdsongError::UnknownReference{token, ..} |
+            BirdsongError::InvalidChoiceTarget{token, ..} |
+            BirdsongError::DuplicateLabel{token, ..} |
+            BirdsongError::InvalidExpression{token, ..} |
+            BirdsongError::InvalidCommand{token, ..} |
+            BirdsongError::UnknownTag{token, ..} |
+            BirdsongError::UnmatchedTag{token, ..} |
+            BirdsongError::UnknownTransition{token, ..} |
+            BirdsongError::UnknownEasing{token, ..} |
+            BirdsongError::UnknownFitMode{token, ..} |
+            BirdsongError::UnknownBlipMode{token, ..} => token.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for BirdsongError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line(), self.column())?;
+        match self {
+            BirdsongError::MissingSeparator{token, separator, ..} => write!(f, "expected '{}' in '{}'", separator, token),
+            BirdsongError::UnexpectedSeparator{token, separator, ..} => write!(f, "unexpected '{}' in '{}'", separator, token),
+            BirdsongError::InvalidNumber{token, ..} => write!(f, "invalid number '{}'", token),
+            BirdsongError::InvalidVector{token, expected, ..} => write!(f, "expected {} values separated by 'x' in '{}'", expected, token),
+            BirdsongError::UnknownEntryType{token, ..} => write!(f, "unknown entry type '{}'", token),
+            BirdsongError::UnknownSetting{token, ..} => write!(f, "unknown setting '{}'", token),
+            BirdsongError::UnknownReference{token, kind, ..} => write!(f, "unknown {} '{}'", kind, token),
+            BirdsongError::InvalidChoiceTarget{token, ..} => write!(f, "invalid choice target '{}'", token),
+            BirdsongError::DuplicateLabel{token, ..} => write!(f, "duplicate label '{}'", token),
+            BirdsongError::InvalidExpression{token, reason, ..} => write!(f, "invalid expression near '{}': {}", token, reason),
+            BirdsongError::InvalidCommand{token, ..} => write!(f, "invalid command '{}'", token),
+            BirdsongError::UnknownTag{token, ..} => write!(f, "unknown tag '[{}]'", token),
+            BirdsongError::UnmatchedTag{token, ..} => write!(f, "'[{}]' does not close an open tag", token),
+            BirdsongError::UnknownTransition{token, ..} => write!(f, "unknown transition '{}'", token),
+            BirdsongError::UnknownEasing{token, ..} => write!(f, "unknown easing '{}'", token),
+            BirdsongError::UnknownFitMode{token, ..} => write!(f, "unknown fit mode '{}'", token),
+            BirdsongError::UnknownBlipMode{token, ..} => write!(f, "unknown blip mode '{}'", token),
+        }
+    }
+}
+
+impl std::error::Error for BirdsongError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub errors: Vec<BirdsongError>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error(s) in script", self.errors.len())?;
+        for error in self.errors.iter() {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
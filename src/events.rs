@@ -0,0 +1,45 @@
+use bevy::prelude::Entity;
+use crate::error::BirdsongError;
+
+// Every event carries the entity of the `DialogueRunner` it comes from.
+
+pub struct BirdsongDiagnosticEvent {
+    pub runner: Entity,
+    pub error: BirdsongError,
+}
+
+pub struct DialogueStartedEvent {
+    pub runner: Entity,
+}
+
+pub struct LineShownEvent {
+    pub runner: Entity,
+    pub entry: usize,
+    pub actor: Option<String>,
+    pub text: String,
+}
+
+pub struct TypewriterFinishedEvent {
+    pub runner: Entity,
+    pub entry: usize,
+}
+
+pub struct ChoicePresentedEvent {
+    pub runner: Entity,
+    pub entry: usize,
+    pub options: Vec<String>,
+}
+
+pub struct ChoiceMadeEvent {
+    pub runner: Entity,
+    pub entry: usize,
+    /// Position of the choice in the `options` of the matching `ChoicePresentedEvent`, which leave out choices whose condition is false.
+    pub index: usize,
+    /// Position of the choice in its script entry, counting the choices that were hidden.
+    pub item: usize,
+    pub target: usize,
+}
+
+pub struct DialogueEndedEvent {
+    pub runner: Entity,
+}
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Var(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates the expression against a variable store. Unset variables evaluate to `false`.
+    pub fn eval(&self, variables: &HashMap<String, Value>) -> Result<Value, String> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Var(name) => Ok(variables.get(name).cloned().unwrap_or(Value::Bool(false))),
+            Expr::Not(expr) => Ok(Value::Bool(!expr.eval(variables)?.is_truthy())),
+            Expr::Neg(expr) => match expr.eval(variables)? {
+                Value::Int(n) => Ok(Value::Int(n.wrapping_neg())),
+                value => Err(format!("cannot negate '{}'", value)),
+            },
+            Expr::Binary(BinaryOp::And, lhs, rhs) => Ok(Value::Bool(lhs.eval(variables)?.is_truthy() && rhs.eval(variables)?.is_truthy())),
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => Ok(Value::Bool(lhs.eval(variables)?.is_truthy() || rhs.eval(variables)?.is_truthy())),
+            Expr::Binary(op, lhs, rhs) => eval_binary(*op, lhs.eval(variables)?, rhs.eval(variables)?),
+        }
+    }
+}
+
+fn eval_binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (op, lhs, rhs) {
+        (BinaryOp::Eq, lhs, rhs) => Ok(Value::Bool(lhs == rhs)),
+        (BinaryOp::Ne, lhs, rhs) => Ok(Value::Bool(lhs != rhs)),
+        (BinaryOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
+        (BinaryOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+        (BinaryOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+        (BinaryOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+        (BinaryOp::Lt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a < b)),
+        (BinaryOp::Le, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a <= b)),
+        (BinaryOp::Gt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a > b)),
+        (BinaryOp::Ge, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a >= b)),
+        (BinaryOp::Add, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_add(b))),
+        (BinaryOp::Add, Value::Str(a), b) => Ok(Value::Str(format!("{}{}", a, b))),
+        (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_sub(b))),
+        (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_mul(b))),
+        (BinaryOp::Div, Value::Int(_), Value::Int(0)) => Err("division by zero".to_string()),
+        (BinaryOp::Div, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_div(b))),
+        (op, lhs, rhs) => Err(format!("cannot apply {:?} to '{}' and '{}'", op, lhs, rhs)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub offset: usize,
+    pub token: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+struct Lexeme {
+    token: Token,
+    offset: usize,
+    text: String,
+}
+
+// Longer operators first, so that `<=` is not read as `<` followed by `=`.
+const OPERATORS: [&str; 12] = ["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "!", "="];
+
+fn tokenize(source: &str) -> Result<Vec<Lexeme>, ExprError> {
+    let mut lexemes = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = offset;
+            while let Some(&(i, d)) = chars.peek() {
+                if !d.is_ascii_digit() {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            let text = &source[offset..end];
+            let value = text.parse::<i64>().map_err(|_| ExprError{offset, token: text.to_string(), reason: "integer out of range".to_string()})?;
+            lexemes.push(Lexeme{token: Token::Int(value), offset, text: text.to_string()});
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = offset;
+            while let Some(&(i, d)) = chars.peek() {
+                if !(d.is_alphanumeric() || d == '_' || d == '.') {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            let text = &source[offset..end];
+            lexemes.push(Lexeme{token: Token::Ident(text.to_string()), offset, text: text.to_string()});
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            let mut closed = false;
+            for (_, d) in chars.by_ref() {
+                if d == '"' {
+                    closed = true;
+                    break;
+                }
+                value.push(d);
+            }
+            if !closed {
+                return Err(ExprError{offset, token: source[offset..].to_string(), reason: "unterminated string".to_string()});
+            }
+            lexemes.push(Lexeme{token: Token::Str(value.clone()), offset, text: format!("\"{}\"", value)});
+        } else if c == '(' || c == ')' {
+            chars.next();
+            lexemes.push(Lexeme{token: if c == '(' { Token::LParen } else { Token::RParen }, offset, text: c.to_string()});
+        } else {
+            let op = OPERATORS.iter().find(|op| source[offset..].starts_with(*op)).ok_or_else(|| ExprError{offset, token: c.to_string(), reason: "unexpected character".to_string()})?;
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            lexemes.push(Lexeme{token: Token::Op(op), offset, text: op.to_string()});
+        }
+    }
+    Ok(lexemes)
+}
+
+struct ExprParser<'a> {
+    source: &'a str,
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+}
+
+/// Parses a condition or assignment expression, e.g. `has_key and gold >= 10`.
+pub fn parse_expr(source: &str) -> Result<Expr, ExprError> {
+    let mut parser = ExprParser{source, lexemes: tokenize(source)?, pos: 0};
+    let expr = parser.parse_or()?;
+    if parser.pos < parser.lexemes.len() {
+        return Err(parser.unexpected("expected end of expression"));
+    }
+    Ok(expr)
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.pos).map(|lexeme| &lexeme.token)
+    }
+
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        if let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if ops.contains(&op) {
+                self.pos += 1;
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if let Some(Token::Ident(ident)) = self.peek() {
+            if ident == keyword {
+                self.pos += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn unexpected(&self, reason: &str) -> ExprError {
+        match self.lexemes.get(self.pos) {
+            Some(lexeme) => ExprError{offset: lexeme.offset, token: lexeme.text.clone(), reason: reason.to_string()},
+            None => ExprError{offset: self.source.len(), token: "".to_string(), reason: reason.to_string()},
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_and()?;
+        while self.eat_keyword("or") {
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(self.parse_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_not()?;
+        while self.eat_keyword("and") {
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(self.parse_not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ExprError> {
+        if self.eat_keyword("not") || self.eat_op(&["!"]).is_some() {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ExprError> {
+        let lhs = self.parse_additive()?;
+        let op = match self.eat_op(&["==", "!=", "<=", ">=", "<", ">"]) {
+            Some("==") => BinaryOp::Eq,
+            Some("!=") => BinaryOp::Ne,
+            Some("<=") => BinaryOp::Le,
+            Some(">=") => BinaryOp::Ge,
+            Some("<") => BinaryOp::Lt,
+            Some(">") => BinaryOp::Gt,
+            _ => return Ok(lhs),
+        };
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.parse_additive()?)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_multiplicative()?;
+        while let Some(op) = self.eat_op(&["+", "-"]) {
+            let op = if op == "+" { BinaryOp::Add } else { BinaryOp::Sub };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.parse_multiplicative()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = self.eat_op(&["*", "/"]) {
+            let op = if op == "*" { BinaryOp::Mul } else { BinaryOp::Div };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.parse_unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat_op(&["-"]).is_some() {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ExprError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.unexpected("unexpected end of expression")),
+        };
+        match token {
+            Token::Int(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Int(n)))
+            },
+            Token::Str(s) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Str(s)))
+            },
+            Token::Ident(ident) => match ident.as_str() {
+                "true" | "false" => {
+                    self.pos += 1;
+                    Ok(Expr::Literal(Value::Bool(ident == "true")))
+                },
+                "and" | "or" | "not" => Err(self.unexpected("expected a value")),
+                _ => {
+                    self.pos += 1;
+                    Ok(Expr::Var(ident))
+                },
+            },
+            Token::LParen => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.unexpected("expected ')'"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            },
+            _ => Err(self.unexpected("expected a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, variables: &HashMap<String, Value>) -> Result<Value, String> {
+        parse_expr(source).unwrap().eval(variables)
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        let variables = HashMap::new();
+        assert_eq!(eval("1 + 2 * 3", &variables), Ok(Value::Int(7)));
+        assert_eq!(eval("(1 + 2) * 3", &variables), Ok(Value::Int(9)));
+        assert_eq!(eval("10 - 4 - 3", &variables), Ok(Value::Int(3)));
+        assert_eq!(eval("-2 * -3", &variables), Ok(Value::Int(6)));
+        assert_eq!(eval("1 < 2 and not 3 == 4", &variables), Ok(Value::Bool(true)));
+        assert_eq!(eval("false or 1 > 2", &variables), Ok(Value::Bool(false)));
+        assert_eq!(eval("!true", &variables), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn reads_variables() {
+        let mut variables = HashMap::new();
+        variables.insert("gold".to_string(), Value::Int(12));
+        variables.insert("player.name".to_string(), Value::from("Alice"));
+        assert_eq!(eval("gold >= 10", &variables), Ok(Value::Bool(true)));
+        assert_eq!(eval("\"Hi \" + player.name", &variables), Ok(Value::from("Hi Alice")));
+        assert_eq!(eval("has_key", &variables), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn reports_evaluation_errors() {
+        let variables = HashMap::new();
+        assert_eq!(eval("1 / 0", &variables), Err("division by zero".to_string()));
+        assert_eq!(eval("-\"a\"", &variables), Err("cannot negate 'a'".to_string()));
+        assert!(eval("1 + true", &variables).is_err());
+        assert_eq!(eval("9223372036854775807 + 1", &variables), Ok(Value::Int(i64::MIN)));
+        assert_eq!(eval("-(0 - 9223372036854775807 - 1)", &variables), Ok(Value::Int(i64::MIN)));
+    }
+
+    #[test]
+    fn reports_parse_errors_at_their_offset() {
+        assert_eq!(parse_expr("1 +"), Err(ExprError{offset: 3, token: "".to_string(), reason: "unexpected end of expression".to_string()}));
+        assert_eq!(parse_expr("(1 + 2"), Err(ExprError{offset: 6, token: "".to_string(), reason: "expected ')'".to_string()}));
+        assert_eq!(parse_expr("1 2"), Err(ExprError{offset: 2, token: "2".to_string(), reason: "expected end of expression".to_string()}));
+        assert_eq!(parse_expr("a @ b"), Err(ExprError{offset: 2, token: "@".to_string(), reason: "unexpected character".to_string()}));
+        assert_eq!(parse_expr("\"open"), Err(ExprError{offset: 0, token: "\"open".to_string(), reason: "unterminated string".to_string()}));
+        assert_eq!(parse_expr("99999999999999999999"), Err(ExprError{offset: 0, token: "99999999999999999999".to_string(), reason: "integer out of range".to_string()}));
+        assert_eq!(parse_expr("a and or b"), Err(ExprError{offset: 6, token: "or".to_string(), reason: "expected a value".to_string()}));
+    }
+}
@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BirdsongAction {
+    // Prints the whole line if it is still printing, otherwise moves on or confirms the highlighted choice.
+    Advance,
+    // Prints the whole line without moving on.
+    Skip,
+    Up,
+    Down,
+    // Confirms the highlighted choice.
+    Confirm,
+    // Ends the dialogue.
+    Cancel,
+    // Speeds up the typewriter and moves through text lines while held.
+    FastForward,
+}
+
+/// Feeds an action to the runners as if it had been pressed. Without a `runner`, it goes to every runner accepting input.
+pub struct BirdsongActionEvent {
+    pub action: BirdsongAction,
+    pub runner: Option<Entity>,
+}
+
+pub struct BirdsongInput {
+    keys: HashMap<BirdsongAction, Vec<KeyCode>>,
+    gamepad_buttons: HashMap<BirdsongAction, Vec<GamepadButtonType>>,
+    mouse_buttons: HashMap<BirdsongAction, Vec<MouseButton>>,
+    pub fast_forward_speed: f32,
+    /// Lets the mouse and touches hover and pick choices, and tap the dialogue box to advance.
+    pub pointer: bool,
+}
+
+impl Default for BirdsongInput {
+    fn default() -> BirdsongInput {
+        let mut input = BirdsongInput{keys: HashMap::new(), gamepad_buttons: HashMap::new(), mouse_buttons: HashMap::new(), fast_forward_speed: 5., pointer: true};
+        input
+            .bind_key(BirdsongAction::Advance, KeyCode::Space)
+            .bind_key(BirdsongAction::Advance, KeyCode::Return)
+            .bind_key(BirdsongAction::Up, KeyCode::Up)
+            .bind_key(BirdsongAction::Up, KeyCode::Z)
+            .bind_key(BirdsongAction::Up, KeyCode::W)
+            .bind_key(BirdsongAction::Down, KeyCode::Down)
+            .bind_key(BirdsongAction::Down, KeyCode::S)
+            .bind_key(BirdsongAction::FastForward, KeyCode::LControl)
+            .bind_key(BirdsongAction::FastForward, KeyCode::RControl)
+            .bind_gamepad_button(BirdsongAction::Advance, GamepadButtonType::South)
+            .bind_gamepad_button(BirdsongAction::Up, GamepadButtonType::DPadUp)
+            .bind_gamepad_button(BirdsongAction::Down, GamepadButtonType::DPadDown)
+            .bind_gamepad_button(BirdsongAction::FastForward, GamepadButtonType::RightTrigger2);
+        input
+    }
+}
+
+impl BirdsongInput {
+    pub fn bind_key(&mut self, action: BirdsongAction, key: KeyCode) -> &mut Self {
+        self.keys.entry(action).or_default().push(key);
+        self
+    }
+
+    /// Gamepad buttons are read on every connected gamepad.
+    pub fn bind_gamepad_button(&mut self, action: BirdsongAction, button: GamepadButtonType) -> &mut Self {
+        self.gamepad_buttons.entry(action).or_default().push(button);
+        self
+    }
+
+    pub fn bind_mouse_button(&mut self, action: BirdsongAction, button: MouseButton) -> &mut Self {
+        self.mouse_buttons.entry(action).or_default().push(button);
+        self
+    }
+
+    /// Removes every binding of an action.
+    pub fn unbind(&mut self, action: BirdsongAction) -> &mut Self {
+        self.keys.remove(&action);
+        self.gamepad_buttons.remove(&action);
+        self.mouse_buttons.remove(&action);
+        self
+    }
+
+    pub fn keys(&self, action: BirdsongAction) -> &[KeyCode] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    pub fn gamepad_buttons(&self, action: BirdsongAction) -> &[GamepadButtonType] {
+        self.gamepad_buttons.get(&action).map_or(&[], |buttons| buttons.as_slice())
+    }
+
+    pub fn mouse_buttons(&self, action: BirdsongAction) -> &[MouseButton] {
+        self.mouse_buttons.get(&action).map_or(&[], |buttons| buttons.as_slice())
+    }
+
+    pub(crate) fn just_pressed(&self, action: BirdsongAction, devices: &InputDevices) -> bool {
+        self.keys(action).iter().any(|key| devices.keys.just_pressed(*key))
+            || self.mouse_buttons(action).iter().any(|button| devices.mouse_buttons.just_pressed(*button))
+            || self.gamepad_buttons(action).iter().any(|button| devices.gamepads.iter().any(|gamepad| devices.gamepad_buttons.just_pressed(GamepadButton::new(*gamepad, *button))))
+    }
+
+    pub(crate) fn pressed(&self, action: BirdsongAction, devices: &InputDevices) -> bool {
+        self.keys(action).iter().any(|key| devices.keys.pressed(*key))
+            || self.mouse_buttons(action).iter().any(|button| devices.mouse_buttons.pressed(*button))
+            || self.gamepad_buttons(action).iter().any(|button| devices.gamepads.iter().any(|gamepad| devices.gamepad_buttons.pressed(GamepadButton::new(*gamepad, *button))))
+    }
+}
+
+pub(crate) struct InputDevices<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse_buttons: &'a Input<MouseButton>,
+    pub gamepad_buttons: &'a Input<GamepadButton>,
+    pub gamepads: &'a Gamepads,
+}
+
+pub(crate) const ALL_ACTIONS: [BirdsongAction; 7] = [BirdsongAction::Advance, BirdsongAction::Skip, BirdsongAction::Up, BirdsongAction::Down, BirdsongAction::Confirm, BirdsongAction::Cancel, BirdsongAction::FastForward];
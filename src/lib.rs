@@ -1,8 +1,30 @@
+mod asset;
 mod birdsong;
+pub mod command;
 mod components;
+mod panel;
+pub mod error;
+pub mod events;
+pub mod expr;
+pub mod input;
+pub mod rng;
+pub mod script;
 
 pub mod prelude {
     #[doc(hidden)]
-    pub use crate::birdsong::{Birdsong, BirdsongPlugin};
+    pub use crate::asset::BirdsongScript;
+    #[doc(hidden)]
+    pub use crate::birdsong::{Birdsong, BirdsongPlugin, DialogueRunner, DialogueRunnerBundle};
+    #[doc(hidden)]
+    pub use crate::command::{BirdsongAppExt, CommandStatus};
+    #[doc(hidden)]
+    pub use crate::error::{BirdsongError, ParseError};
+    #[doc(hidden)]
+    pub use crate::events::{BirdsongDiagnosticEvent, DialogueStartedEvent, LineShownEvent, TypewriterFinishedEvent, ChoicePresentedEvent, ChoiceMadeEvent, DialogueEndedEvent};
+    #[doc(hidden)]
+    pub use crate::input::{BirdsongAction, BirdsongActionEvent, BirdsongInput};
+    #[doc(hidden)]
+    pub use crate::rng::BirdsongRng;
+    #[doc(hidden)]
+    pub use crate::script::{parse_script, Script, Entry, StyleRun, RunStyle, ControlTag, TypewriterControl, StageSlot, Transition, TransitionKind, Easing, BackgroundFit, BackgroundLayer, HideTarget, BlipMode};
 }
-
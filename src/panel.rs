@@ -0,0 +1,43 @@
+use bevy::prelude::*;
+use bevy::sprite::Rect;
+
+// Splits an image into a 3x3 grid of pieces `border` pixels from each edge, rows read from the top.
+pub(crate) fn nine_slice_atlas(image: Handle<Image>, image_size: Vec2, border: f32) -> TextureAtlas {
+    let xs = [0., border, image_size.x - border, image_size.x];
+    let ys = [0., border, image_size.y - border, image_size.y];
+    let mut atlas = TextureAtlas::new_empty(image, image_size);
+    for row in 0..3 {
+        for col in 0..3 {
+            atlas.add_texture(Rect{min: Vec2::new(xs[col], ys[row]), max: Vec2::new(xs[col + 1], ys[row + 1])});
+        }
+    }
+    atlas
+}
+
+// Spawns a panel of the given size, whose entity is placed at its top-left corner.
+// Corners keep their size while the edges and the center stretch.
+pub(crate) fn spawn_nine_slice(commands: &mut Commands, atlas: Handle<TextureAtlas>, border: f32, top_left: Vec3, size: Vec2) -> Entity {
+    let widths = [border, (size.x - 2. * border).max(0.), border];
+    let heights = [border, (size.y - 2. * border).max(0.), border];
+    commands.spawn_bundle(SpatialBundle {
+        transform: Transform::from_translation(top_left),
+        ..default()
+    })
+    .with_children(|parent| {
+        let mut y = 0.;
+        for (row, height) in heights.iter().enumerate() {
+            let mut x = 0.;
+            for (col, width) in widths.iter().enumerate() {
+                parent.spawn_bundle(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite{custom_size: Some(Vec2::new(*width, *height)), ..TextureAtlasSprite::new(row * 3 + col)},
+                    texture_atlas: atlas.clone(),
+                    transform: Transform::from_xyz(x + width / 2., -y - height / 2., 0.),
+                    ..default()
+                });
+                x += width;
+            }
+            y += height;
+        }
+    })
+    .id()
+}
@@ -0,0 +1,77 @@
+/// A small xorshift generator, so that voice blips vary the same way for the same seed.
+#[derive(Debug, Clone)]
+pub struct BirdsongRng {
+    state: u64,
+}
+
+// xorshift never leaves the zero state, so a zero seed is replaced.
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+impl Default for BirdsongRng {
+    fn default() -> BirdsongRng {
+        BirdsongRng::new(DEFAULT_SEED)
+    }
+}
+
+impl BirdsongRng {
+    pub fn new(seed: u64) -> BirdsongRng {
+        BirdsongRng{state: if seed == 0 { DEFAULT_SEED } else { seed }}
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A value in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A value in `[min, max)`, or `min` when the range is empty.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min).max(0.) * self.next_f32()
+    }
+
+    /// An index below `len`, which must not be zero.
+    pub fn index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_for_the_same_seed() {
+        let mut a = BirdsongRng::new(42);
+        let mut b = BirdsongRng::new(42);
+        let mut c = BirdsongRng::new(43);
+        let first: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        assert_eq!(first, (0..8).map(|_| b.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(first, (0..8).map(|_| c.next_u64()).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn replaces_a_zero_seed() {
+        let mut rng = BirdsongRng::new(0);
+        assert_ne!(rng.next_u64(), 0);
+    }
+
+    #[test]
+    fn stays_in_bounds() {
+        let mut rng = BirdsongRng::new(7);
+        for _ in 0..1000 {
+            let value = rng.next_f32();
+            assert!((0. ..1.).contains(&value));
+            let value = rng.range(0.9, 1.1);
+            assert!((0.9..1.1).contains(&value));
+            assert!(rng.index(3) < 3);
+        }
+        assert_eq!(rng.range(1.2, 1.2), 1.2);
+        assert_eq!(rng.range(2., 1.), 2.);
+    }
+}
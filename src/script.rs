@@ -0,0 +1,1073 @@
+use bevy::prelude::{Color, Vec2, Vec3};
+use std::collections::HashMap;
+use crate::error::{BirdsongError, ParseError};
+use crate::expr::{parse_expr, Expr};
+
+const DEFAULT_TRANSITION_DURATION: f32 = 0.5;
+
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    pub fonts: HashMap<String, String>,
+    pub cursor_sprites: HashMap<String, String>,
+    pub backgrounds: HashMap<String, BackgroundDecl>,
+    pub panels: HashMap<String, PanelDecl>,
+    pub actors: HashMap<String, ActorDecl>,
+    pub voice_lines: HashMap<String, VoiceLineDecl>,
+    pub blips: HashMap<String, BlipDecl>,
+    pub labels: HashMap<String, usize>,
+    pub entries: Vec<Entry>,
+}
+
+/// Backgrounds sharing the same `z` form a layer, where showing one replaces the other.
+/// `parallax` is the fraction of the camera movement the layer follows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundDecl {
+    pub path: String,
+    pub pos: Vec2,
+    pub fit: BackgroundFit,
+    pub z: f32,
+    pub parallax: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundFit {
+    // Image size
+    Native,
+    // Window size, ignoring the aspect ratio
+    Stretch,
+    // Smallest size covering the window
+    Cover,
+    // Largest size fitting in the window
+    Contain,
+}
+
+/// A nine-sliced frame image, `border` being the size in pixels of its corners.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelDecl {
+    pub path: String,
+    pub border: f32,
+}
+
+/// `name` is the display name, which defaults to the actor id. Expressions map a name to a portrait path.
+/// Each voice blip picks one of `voices` at random.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActorDecl {
+    pub name: String,
+    pub portrait: String,
+    pub voices: Vec<String>,
+    pub expressions: HashMap<String, String>,
+}
+
+/// How an actor's voice blips play. `pitch` and `volume` are the ranges each blip picks its playback speed and volume from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlipDecl {
+    pub mode: BlipMode,
+    pub pitch: Vec2,
+    pub volume: Vec2,
+}
+
+impl Default for BlipDecl {
+    fn default() -> BlipDecl {
+        BlipDecl{mode: BlipMode::Timer, pitch: Vec2::ONE, volume: Vec2::ONE}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlipMode {
+    // Every `voice_frequency` seconds while the line prints
+    Timer,
+    // On each revealed letter or digit
+    Character,
+    // On each revealed group of vowels
+    Syllable,
+}
+
+/// A voice-over clip for a whole line. With a `duration` (in seconds), the typewriter prints the line over that time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceLineDecl {
+    pub path: String,
+    pub duration: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Settings(Vec<Setting>),
+    Choice(Vec<ChoiceItem>),
+    Text { actor: Option<String>, expression: Option<String>, text: String, runs: Vec<StyleRun>, controls: Vec<ControlTag>, voice: Option<String> },
+    Image { name: String, transition: Option<Transition> },
+    Enter { actor: String, expression: Option<String>, slot: StageSlot, transition: Option<Transition> },
+    Exit { actor: String, transition: Option<Transition> },
+    Hide { target: HideTarget, transition: Option<Transition> },
+    Label(String),
+    Goto(usize),
+    Set { name: String, value: Expr },
+    If { condition: Expr, next: usize },
+    Command { name: String, args: Vec<String> },
+}
+
+/// A stretch of a text entry sharing the same inline style, ending at the byte offset `end` of the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleRun {
+    pub end: usize,
+    pub style: RunStyle,
+}
+
+/// Inline style set by `[color=..]`, `[font=..]` and `[size=..]` tags, and the `[shake]`, `[wave]` and `[rainbow]` effects. Unset values fall back to the dialogue box settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStyle {
+    pub color: Option<Color>,
+    pub font: Option<String>,
+    pub size: Option<f32>,
+    pub shake: bool,
+    pub wave: bool,
+    pub rainbow: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HideTarget {
+    // Every background layer, or only the given one
+    Background(Option<BackgroundLayer>),
+    Portrait,
+}
+
+/// A background layer, given by the name of a background shown in it or by its `z`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundLayer {
+    Named(String),
+    Z(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StageSlot {
+    Left,
+    Center,
+    Right,
+    Custom(Vec3),
+}
+
+/// Written `kind:duration:easing` after a `|`, the duration (in seconds) and easing being optional.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionKind {
+    Crossfade,
+    // Through black
+    Fade,
+    // In from, or out to, the given side
+    SlideLeft,
+    SlideRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// A typewriter control tag, taking effect once the text has been revealed up to the byte offset `at`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlTag {
+    pub at: usize,
+    pub control: TypewriterControl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypewriterControl {
+    // `[pause=seconds]`
+    Pause(f32),
+    // `[speed=characters per second]`, or `None` to go back to the box text speed.
+    Speed(Option<f32>),
+    // `[wait]`: stops until the player advances.
+    Wait,
+    // `[instant]` and `[/instant]`
+    Instant(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceItem {
+    pub text: String,
+    pub next: usize,
+    pub condition: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Font(String),
+    FontSize(f32),
+    FontColor(Color),
+    Cursor(String),
+    BoxSize(Vec2),
+    BoxPosition(Vec3),
+    BoxTextSpeed(f32),
+    BoxFadeIn(f32),
+    Panel(String),
+    PanelPadding(Vec2),
+    Nameplate(String),
+    NameplateOffset(Vec3),
+    NameplateSize(Vec2),
+    StageDim(Color),
+    // `none` turns it off
+    PortraitTransition(Option<Transition>),
+    VoiceFrequency(f32),
+    ChoiceSpacing(f32),
+    ChoiceIndent(f32),
+    CursorOffset(f32),
+    PortraitPosition(Vec3),
+}
+
+enum Section {
+    Fonts,
+    CursorSprites,
+    Backgrounds,
+    Panels,
+    Actors,
+    VoiceLines,
+    Blips,
+    Entries,
+}
+
+#[derive(Clone, Copy)]
+enum ReferenceKind {
+    Font,
+    Cursor,
+    Background,
+    Panel,
+    Actor,
+    Expression,
+    VoiceLine,
+}
+
+struct Reference {
+    kind: ReferenceKind,
+    name: String,
+    line: usize,
+    column: usize,
+}
+
+// A choice item or goto destination, resolved to an entry index once every label is known.
+struct JumpTarget {
+    entry: usize,
+    item: Option<usize>,
+    line: usize,
+    column: usize,
+    token: String,
+}
+
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    text: &'a str,
+    num: usize,
+}
+
+impl<'a> Line<'a> {
+    // Tokens are always subslices of the line, so their column can be recovered from the pointer offset.
+    fn column(&self, token: &str) -> usize {
+        let offset = (token.as_ptr() as usize).saturating_sub(self.text.as_ptr() as usize).min(self.text.len());
+        self.text[..offset].chars().count() + 1
+    }
+}
+
+// The text of an entry with its inline tags taken out.
+struct Markup {
+    text: String,
+    runs: Vec<StyleRun>,
+    controls: Vec<ControlTag>,
+    voice: Option<String>,
+}
+
+// An `actor:expression#path` line, added to its actor once the whole section is read.
+struct ExpressionDecl {
+    actor: String,
+    expression: String,
+    path: String,
+}
+
+#[derive(Default)]
+struct Parser {
+    errors: Vec<BirdsongError>,
+    references: Vec<Reference>,
+    expressions: Vec<ExpressionDecl>,
+    targets: Vec<JumpTarget>,
+    labels: HashMap<String, usize>,
+}
+
+/// Parses a Birdsong script into its typed representation, without touching any Bevy state.
+/// Every malformed line is reported, not only the first one.
+pub fn parse_script(source: &str) -> Result<Script, ParseError> {
+    let mut script = Script::default();
+    let mut parser = Parser::default();
+    let mut section = Section::Fonts;
+
+    for (line_idx, text) in source.lines().enumerate() {
+        let line = Line{text, num: line_idx + 1};
+        match text.trim_end() {
+            "## FONTS" => {
+                section = Section::Fonts;
+                continue;
+            },
+            "## CURSOR SPRITES" => {
+                section = Section::CursorSprites;
+                continue;
+            },
+            "## BACKGROUNDS" => {
+                section = Section::Backgrounds;
+                continue;
+            },
+            "## PANELS" => {
+                section = Section::Panels;
+                continue;
+            },
+            "## ACTORS" => {
+                section = Section::Actors;
+                continue;
+            },
+            "## VOICE LINES" => {
+                section = Section::VoiceLines;
+                continue;
+            },
+            "## BLIPS" => {
+                section = Section::Blips;
+                continue;
+            },
+            "## ENTRIES" => {
+                section = Section::Entries;
+                continue;
+            },
+            "" => continue,
+            _ => (),
+        }
+        if let Err(err) = parser.parse_line(&mut script, &section, line) {
+            parser.errors.push(err);
+        }
+    }
+    parser.check_references(&mut script);
+
+    if parser.errors.is_empty() {
+        Ok(script)
+    } else {
+        Err(ParseError{errors: parser.errors})
+    }
+}
+
+impl Parser {
+    fn parse_line(&mut self, script: &mut Script, section: &Section, line: Line) -> Result<(), BirdsongError> {
+        let (key, value) = split_pair(line.text, '#', line)?;
+        match section {
+            Section::Fonts => {
+                script.fonts.insert(key.to_string(), value.to_string());
+            },
+            Section::CursorSprites => {
+                script.cursor_sprites.insert(key.to_string(), value.to_string());
+            },
+            Section::Backgrounds => {
+                let mut options = value.split('|');
+                let (path, pos) = split_pair(options.next().unwrap_or(""), '@', line)?;
+                let mut bg = BackgroundDecl{path: path.to_string(), pos: parse_vec2(pos, line)?, fit: BackgroundFit::Native, z: 0., parallax: 0.};
+                for option in options {
+                    match split_pair(option, ':', line)? {
+                        ("fit", "native") => bg.fit = BackgroundFit::Native,
+                        ("fit", "stretch") => bg.fit = BackgroundFit::Stretch,
+                        ("fit", "cover") => bg.fit = BackgroundFit::Cover,
+                        ("fit", "contain") => bg.fit = BackgroundFit::Contain,
+                        ("fit", fit) => return Err(BirdsongError::UnknownFitMode{line: line.num, column: line.column(fit), token: fit.to_string()}),
+                        ("z", z) => bg.z = parse_f32(z, line)?,
+                        ("parallax", parallax) => bg.parallax = parse_f32(parallax, line)?,
+                        (name, _) => return Err(BirdsongError::UnknownSetting{line: line.num, column: line.column(name), token: name.to_string()}),
+                    }
+                }
+                script.backgrounds.insert(key.to_string(), bg);
+            },
+            Section::Panels => {
+                let (path, border) = split_pair(value, '|', line)?;
+                script.panels.insert(key.to_string(), PanelDecl{path: path.to_string(), border: parse_f32(border, line)?});
+            },
+            Section::Actors => {
+                if let Some((actor, expression)) = key.split_once(':') {
+                    self.reference(ReferenceKind::Actor, actor, line);
+                    self.expressions.push(ExpressionDecl{actor: actor.to_string(), expression: expression.to_string(), path: value.to_string()});
+                    return Ok(());
+                }
+                let (portrait, rest) = split_pair(value, '|', line)?;
+                let (voice, name) = rest.split_once('|').unwrap_or((rest, key));
+                let voices = voice.split(',').map(|voice| voice.to_string()).collect();
+                script.actors.insert(key.to_string(), ActorDecl{name: name.to_string(), portrait: portrait.to_string(), voices, expressions: HashMap::new()});
+            },
+            Section::VoiceLines => {
+                let (path, duration) = match value.split_once('|') {
+                    Some((path, duration)) => (path, Some(parse_positive_f32(duration, line)?)),
+                    None => (value, None),
+                };
+                script.voice_lines.insert(key.to_string(), VoiceLineDecl{path: path.to_string(), duration});
+            },
+            Section::Blips => {
+                self.reference(ReferenceKind::Actor, key, line);
+                let mut blip = BlipDecl::default();
+                for option in value.split('|') {
+                    match split_pair(option, ':', line)? {
+                        ("mode", "timer") => blip.mode = BlipMode::Timer,
+                        ("mode", "character") => blip.mode = BlipMode::Character,
+                        ("mode", "syllable") => blip.mode = BlipMode::Syllable,
+                        ("mode", mode) => return Err(BirdsongError::UnknownBlipMode{line: line.num, column: line.column(mode), token: mode.to_string()}),
+                        ("pitch", pitch) => blip.pitch = parse_range(pitch, line)?,
+                        ("volume", volume) => blip.volume = parse_range(volume, line)?,
+                        (name, _) => return Err(BirdsongError::UnknownSetting{line: line.num, column: line.column(name), token: name.to_string()}),
+                    }
+                }
+                script.blips.insert(key.to_string(), blip);
+            },
+            Section::Entries => {
+                let entry = self.parse_entry(key, value, script.entries.len(), line)?;
+                script.entries.push(entry);
+            },
+        }
+        Ok(())
+    }
+
+    // Errors in individual settings or choice items are collected so that a single line can report several of them.
+    fn parse_entry(&mut self, entry_type: &str, value: &str, index: usize, line: Line) -> Result<Entry, BirdsongError> {
+        match entry_type {
+            "s" => {
+                let mut settings = Vec::new();
+                for setting in value.split('|') {
+                    match self.parse_setting(setting, line) {
+                        Ok(setting) => settings.push(setting),
+                        Err(err) => self.errors.push(err),
+                    }
+                }
+                Ok(Entry::Settings(settings))
+            },
+            "c" => {
+                let mut items = Vec::new();
+                for choice in value.split('|') {
+                    match self.parse_choice(choice, index, items.len(), line) {
+                        Ok(item) => items.push(item),
+                        Err(err) => self.errors.push(err),
+                    }
+                }
+                Ok(Entry::Choice(items))
+            },
+            "t" => {
+                let entry_vec: Vec<&str> = value.split('@').collect();
+                match entry_vec.len() {
+                    1 => {
+                        let Markup{text, runs, controls, voice} = self.parse_markup(entry_vec[0], line)?;
+                        Ok(Entry::Text{actor: None, expression: None, text, runs, controls, voice})
+                    },
+                    2 => {
+                        let (actor, expression) = self.parse_actor(entry_vec[0], line);
+                        let Markup{text, runs, controls, voice} = self.parse_markup(entry_vec[1], line)?;
+                        Ok(Entry::Text{actor: Some(actor.to_string()), expression, text, runs, controls, voice})
+                    },
+                    _ => Err(BirdsongError::UnexpectedSeparator{line: line.num, column: line.column(entry_vec[2]) - 1, token: value.to_string(), separator: '@'}),
+                }
+            },
+            "enter" => {
+                let (value, transition) = parse_transition(value, line)?;
+                let (actor, slot) = split_pair(value, '@', line)?;
+                let (actor, expression) = self.parse_actor(actor, line);
+                let slot = match slot {
+                    "left" => StageSlot::Left,
+                    "center" => StageSlot::Center,
+                    "right" => StageSlot::Right,
+                    _ => StageSlot::Custom(parse_vec3(slot, line)?),
+                };
+                Ok(Entry::Enter{actor: actor.to_string(), expression, slot, transition})
+            },
+            "exit" => {
+                let (actor, transition) = parse_transition(value, line)?;
+                self.reference(ReferenceKind::Actor, actor, line);
+                Ok(Entry::Exit{actor: actor.to_string(), transition})
+            },
+            "hide" => {
+                let (target, transition) = parse_transition(value, line)?;
+                match target.split_once(':') {
+                    Some(("background", layer)) => {
+                        let layer = match layer.trim().parse::<f32>() {
+                            Ok(z) => BackgroundLayer::Z(z),
+                            Err(_) => {
+                                self.reference(ReferenceKind::Background, layer, line);
+                                BackgroundLayer::Named(layer.to_string())
+                            },
+                        };
+                        return Ok(Entry::Hide{target: HideTarget::Background(Some(layer)), transition});
+                    },
+                    Some(_) => return Err(BirdsongError::UnknownReference{line: line.num, column: line.column(target), token: target.to_string(), kind: "hide target"}),
+                    None => (),
+                }
+                match target {
+                    "background" => Ok(Entry::Hide{target: HideTarget::Background(None), transition}),
+                    // The portrait only transitions through the `portrait_transition` setting.
+                    "portrait" if transition.is_none() => Ok(Entry::Hide{target: HideTarget::Portrait, transition}),
+                    "portrait" => Err(BirdsongError::UnexpectedSeparator{line: line.num, column: line.column(value) + target.len(), token: value.to_string(), separator: '|'}),
+                    _ => Err(BirdsongError::UnknownReference{line: line.num, column: line.column(target), token: target.to_string(), kind: "hide target"}),
+                }
+            },
+            "i" => {
+                let (name, transition) = parse_transition(value, line)?;
+                self.reference(ReferenceKind::Background, name, line);
+                Ok(Entry::Image{name: name.to_string(), transition})
+            },
+            "l" => {
+                if self.labels.contains_key(value) {
+                    return Err(BirdsongError::DuplicateLabel{line: line.num, column: line.column(value), token: value.to_string()});
+                }
+                self.labels.insert(value.to_string(), index);
+                Ok(Entry::Label(value.to_string()))
+            },
+            "goto" => {
+                self.targets.push(JumpTarget{entry: index, item: None, line: line.num, column: line.column(value), token: value.to_string()});
+                Ok(Entry::Goto(0))
+            },
+            "set" => {
+                let (name, expr) = split_pair(value, '=', line)?;
+                let name_trimmed = name.trim();
+                if name_trimmed.is_empty() || !name_trimmed.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+                    return Err(BirdsongError::InvalidExpression{line: line.num, column: line.column(name), token: name.to_string(), reason: "expected a variable name".to_string()});
+                }
+                Ok(Entry::Set{name: name_trimmed.to_string(), value: parse_expression(expr, line)?})
+            },
+            "cmd" => {
+                let mut args = value.split('|');
+                let name = args.next().unwrap_or("").trim();
+                if name.is_empty() {
+                    return Err(BirdsongError::InvalidCommand{line: line.num, column: line.column(value), token: value.to_string()});
+                }
+                Ok(Entry::Command{name: name.to_string(), args: args.map(|arg| arg.to_string()).collect()})
+            },
+            "if" => {
+                let (condition, next) = split_pair(value, '@', line)?;
+                let condition = parse_expression(condition, line)?;
+                self.targets.push(JumpTarget{entry: index, item: None, line: line.num, column: line.column(next), token: next.to_string()});
+                Ok(Entry::If{condition, next: 0})
+            },
+            _ => Err(BirdsongError::UnknownEntryType{line: line.num, column: line.column(entry_type), token: entry_type.to_string()}),
+        }
+    }
+
+    fn parse_setting(&mut self, setting: &str, line: Line) -> Result<Setting, BirdsongError> {
+        let (name, value) = split_pair(setting, ':', line)?;
+        match name {
+            "font" => {
+                self.reference(ReferenceKind::Font, value, line);
+                Ok(Setting::Font(value.to_string()))
+            },
+            "font_size" => Ok(Setting::FontSize(parse_f32(value, line)?)),
+            "font_color" => Ok(Setting::FontColor(parse_color(value, line)?)),
+            "cursor" => {
+                self.reference(ReferenceKind::Cursor, value, line);
+                Ok(Setting::Cursor(value.to_string()))
+            },
+            "box_size" => Ok(Setting::BoxSize(parse_vec2(value, line)?)),
+            "box_position" => Ok(Setting::BoxPosition(parse_vec3(value, line)?)),
+            "box_text_speed" => Ok(Setting::BoxTextSpeed(parse_positive_f32(value, line)?)),
+            "box_fade_in" => Ok(Setting::BoxFadeIn(parse_f32(value, line)?)),
+            "panel" => {
+                self.reference(ReferenceKind::Panel, value, line);
+                Ok(Setting::Panel(value.to_string()))
+            },
+            "panel_padding" => Ok(Setting::PanelPadding(parse_vec2(value, line)?)),
+            "nameplate" => {
+                self.reference(ReferenceKind::Panel, value, line);
+                Ok(Setting::Nameplate(value.to_string()))
+            },
+            "nameplate_offset" => Ok(Setting::NameplateOffset(parse_vec3(value, line)?)),
+            "nameplate_size" => Ok(Setting::NameplateSize(parse_vec2(value, line)?)),
+            "stage_dim" => Ok(Setting::StageDim(parse_color(value, line)?)),
+            "portrait_transition" if value == "none" => Ok(Setting::PortraitTransition(None)),
+            "portrait_transition" => Ok(Setting::PortraitTransition(Some(parse_transition_spec(value, line)?))),
+            "voice_frequency" => Ok(Setting::VoiceFrequency(parse_f32(value, line)?)),
+            "choice_spacing" => Ok(Setting::ChoiceSpacing(parse_f32(value, line)?)),
+            "choice_indent" => Ok(Setting::ChoiceIndent(parse_f32(value, line)?)),
+            "cursor_offset" => Ok(Setting::CursorOffset(parse_f32(value, line)?)),
+            "portrait_position" => Ok(Setting::PortraitPosition(parse_vec3(value, line)?)),
+            _ => Err(BirdsongError::UnknownSetting{line: line.num, column: line.column(name), token: name.to_string()}),
+        }
+    }
+
+    fn parse_choice(&mut self, choice: &str, entry: usize, item: usize, line: Line) -> Result<ChoiceItem, BirdsongError> {
+        let (text, next) = split_pair(choice, '@', line)?;
+        let (next, condition) = match next.split_once(" if ") {
+            Some((next, condition)) => (next, Some(parse_expression(condition, line)?)),
+            None => (next, None),
+        };
+        self.targets.push(JumpTarget{entry, item: Some(item), line: line.num, column: line.column(next), token: next.to_string()});
+        Ok(ChoiceItem{text: text.to_string(), next: 0, condition})
+    }
+
+    // Splits an `actor:expression` pair, the expression being optional.
+    fn parse_actor<'a>(&mut self, value: &'a str, line: Line) -> (&'a str, Option<String>) {
+        let (actor, expression) = match value.split_once(':') {
+            Some((actor, expression)) => {
+                self.reference(ReferenceKind::Expression, value, line);
+                (actor, Some(expression.to_string()))
+            },
+            None => (value, None),
+        };
+        self.reference(ReferenceKind::Actor, actor, line);
+        (actor, expression)
+    }
+
+    // Strips inline tags from a text entry. `[[` stands for a literal `[`.
+    fn parse_markup(&mut self, value: &str, line: Line) -> Result<Markup, BirdsongError> {
+        let mut text = String::new();
+        let mut runs: Vec<StyleRun> = Vec::new();
+        let mut controls: Vec<ControlTag> = Vec::new();
+        let mut voice: Option<String> = None;
+        let mut style = RunStyle::default();
+        let mut speed: Option<f32> = None;
+        // Open tags, with the style and speed they replaced so that closing them restores it.
+        let mut open: Vec<(&str, RunStyle, Option<f32>)> = Vec::new();
+        let mut rest = value;
+        while let Some(start) = rest.find('[') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(escaped) = rest.strip_prefix("[[") {
+                text.push('[');
+                rest = escaped;
+                continue;
+            }
+            let end = rest.find(']').ok_or_else(|| BirdsongError::MissingSeparator{line: line.num, column: line.column(rest), token: rest.to_string(), separator: ']'})?;
+            let tag = &rest[1..end];
+            let at = text.len();
+            let mut next = style.clone();
+            let mut next_speed = speed;
+            match tag.split_once('=') {
+                Some(("color", color)) => next.color = Some(parse_color(color, line)?),
+                Some(("font", font)) => {
+                    self.reference(ReferenceKind::Font, font, line);
+                    next.font = Some(font.to_string());
+                },
+                Some(("size", size)) => next.size = Some(parse_f32(size, line)?),
+                Some(("speed", value)) => next_speed = Some(parse_positive_f32(value, line)?),
+                Some(("pause", pause)) => controls.push(ControlTag{at, control: TypewriterControl::Pause(parse_positive_f32(pause, line)?)}),
+                Some(("voice", name)) => {
+                    self.reference(ReferenceKind::VoiceLine, name, line);
+                    voice = Some(name.to_string());
+                },
+                None if tag == "wait" => controls.push(ControlTag{at, control: TypewriterControl::Wait}),
+                None if tag == "instant" => controls.push(ControlTag{at, control: TypewriterControl::Instant(true)}),
+                None if tag == "shake" => next.shake = true,
+                None if tag == "wave" => next.wave = true,
+                None if tag == "rainbow" => next.rainbow = true,
+                None if tag.starts_with('/') => {
+                    let name = &tag[1..];
+                    let index = open.iter().rposition(|(open_name, ..)| *open_name == name).ok_or_else(|| BirdsongError::UnmatchedTag{line: line.num, column: line.column(tag), token: tag.to_string()})?;
+                    let (_, previous, previous_speed) = open.remove(index);
+                    match name {
+                        "color" => next.color = previous.color,
+                        "font" => next.font = previous.font,
+                        "size" => next.size = previous.size,
+                        "speed" => next_speed = previous_speed,
+                        "shake" => next.shake = previous.shake,
+                        "wave" => next.wave = previous.wave,
+                        "rainbow" => next.rainbow = previous.rainbow,
+                        _ => controls.push(ControlTag{at, control: TypewriterControl::Instant(open.iter().any(|(open_name, ..)| *open_name == "instant"))}),
+                    }
+                },
+                _ => return Err(BirdsongError::UnknownTag{line: line.num, column: line.column(tag), token: tag.to_string()}),
+            }
+            match tag.split_once('=') {
+                Some(("pause" | "voice", _)) => (),
+                Some((name, _)) => open.push((name, style.clone(), speed)),
+                None if matches!(tag, "instant" | "shake" | "wave" | "rainbow") => open.push((tag, style.clone(), speed)),
+                None => (),
+            }
+            if next_speed != speed {
+                controls.push(ControlTag{at, control: TypewriterControl::Speed(next_speed)});
+                speed = next_speed;
+            }
+            if next != style {
+                push_run(&mut runs, text.len(), style);
+                style = next;
+            }
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        push_run(&mut runs, text.len(), style);
+        Ok(Markup{text, runs, controls, voice})
+    }
+
+    fn reference(&mut self, kind: ReferenceKind, name: &str, line: Line) {
+        self.references.push(Reference{kind, name: name.to_string(), line: line.num, column: line.column(name)});
+    }
+
+    fn check_references(&mut self, script: &mut Script) {
+        for decl in self.expressions.drain(..) {
+            if let Some(actor) = script.actors.get_mut(&decl.actor) {
+                actor.expressions.insert(decl.expression, decl.path);
+            }
+        }
+        for reference in self.references.iter() {
+            let (declared, kind) = match reference.kind {
+                ReferenceKind::Font => (script.fonts.contains_key(&reference.name), "font"),
+                ReferenceKind::Cursor => (script.cursor_sprites.contains_key(&reference.name), "cursor sprite"),
+                ReferenceKind::Background => (script.backgrounds.contains_key(&reference.name), "background"),
+                ReferenceKind::Panel => (script.panels.contains_key(&reference.name), "panel"),
+                ReferenceKind::Actor => (script.actors.contains_key(&reference.name), "actor"),
+                ReferenceKind::VoiceLine => (script.voice_lines.contains_key(&reference.name), "voice line"),
+                ReferenceKind::Expression => {
+                    let (actor, expression) = reference.name.split_once(':').unwrap_or_default();
+                    // An unknown actor is already reported by its own reference.
+                    (!script.actors.contains_key(actor) || script.actors[actor].expressions.contains_key(expression), "expression")
+                },
+            };
+            if !declared {
+                self.errors.push(BirdsongError::UnknownReference{line: reference.line, column: reference.column, token: reference.name.clone(), kind});
+            }
+        }
+        for target in self.targets.iter() {
+            let name = target.token.trim();
+            // Labels take precedence, raw entry indices are still accepted for older scripts.
+            let next = match self.labels.get(name) {
+                Some(index) => *index,
+                None => match name.parse::<usize>() {
+                    Ok(index) if index < script.entries.len() => index,
+                    Ok(_) => {
+                        self.errors.push(BirdsongError::InvalidChoiceTarget{line: target.line, column: target.column, token: target.token.clone()});
+                        continue;
+                    },
+                    Err(_) => {
+                        self.errors.push(BirdsongError::UnknownReference{line: target.line, column: target.column, token: target.token.clone(), kind: "label"});
+                        continue;
+                    },
+                },
+            };
+            match (&mut script.entries[target.entry], target.item) {
+                (Entry::Choice(items), Some(item)) => items[item].next = next,
+                (Entry::Goto(index), None) => *index = next,
+                (Entry::If{next: index, ..}, None) => *index = next,
+                _ => (),
+            }
+        }
+        script.labels = self.labels.clone();
+        self.errors.sort_by_key(|err| (err.line(), err.column()));
+    }
+}
+
+// Runs never end where the previous one did, so an empty line has no runs.
+fn push_run(runs: &mut Vec<StyleRun>, end: usize, style: RunStyle) {
+    if end > runs.last().map_or(0, |run| run.end) {
+        runs.push(StyleRun{end, style});
+    }
+}
+
+fn split_pair<'a>(value: &'a str, separator: char, line: Line) -> Result<(&'a str, &'a str), BirdsongError> {
+    value.split_once(separator).ok_or_else(|| BirdsongError::MissingSeparator{line: line.num, column: line.column(value), token: value.to_string(), separator})
+}
+
+fn parse_expression(value: &str, line: Line) -> Result<Expr, BirdsongError> {
+    parse_expr(value).map_err(|err| BirdsongError::InvalidExpression{line: line.num, column: line.column(&value[err.offset..]), token: err.token, reason: err.reason})
+}
+
+fn parse_f32(value: &str, line: Line) -> Result<f32, BirdsongError> {
+    match value.trim().parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(BirdsongError::InvalidNumber{line: line.num, column: line.column(value), token: value.to_string()}),
+    }
+}
+
+// Speeds and durations that pace the typewriter, where zero or less would stall the line.
+fn parse_positive_f32(value: &str, line: Line) -> Result<f32, BirdsongError> {
+    match parse_f32(value, line)? {
+        number if number > 0. => Ok(number),
+        _ => Err(BirdsongError::InvalidNumber{line: line.num, column: line.column(value), token: value.to_string()}),
+    }
+}
+
+fn parse_floats(value: &str, count: usize, line: Line) -> Result<Vec<f32>, BirdsongError> {
+    let floats = value.split('x').map(|v| parse_f32(v, line)).collect::<Result<Vec<f32>, BirdsongError>>()?;
+    if floats.len() != count {
+        return Err(BirdsongError::InvalidVector{line: line.num, column: line.column(value), token: value.to_string(), expected: count});
+    }
+    Ok(floats)
+}
+
+fn parse_vec2(value: &str, line: Line) -> Result<Vec2, BirdsongError> {
+    let v = parse_floats(value, 2, line)?;
+    Ok(Vec2::new(v[0], v[1]))
+}
+
+// A `minxmax` range, or a single value.
+fn parse_range(value: &str, line: Line) -> Result<Vec2, BirdsongError> {
+    if value.contains('x') {
+        parse_vec2(value, line)
+    } else {
+        Ok(Vec2::splat(parse_f32(value, line)?))
+    }
+}
+
+fn parse_vec3(value: &str, line: Line) -> Result<Vec3, BirdsongError> {
+    let v = parse_floats(value, 3, line)?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+// Splits an optional `|kind:duration:easing` transition off the end of an entry.
+fn parse_transition<'a>(value: &'a str, line: Line) -> Result<(&'a str, Option<Transition>), BirdsongError> {
+    match value.split_once('|') {
+        Some((value, spec)) => Ok((value, Some(parse_transition_spec(spec, line)?))),
+        None => Ok((value, None)),
+    }
+}
+
+fn parse_transition_spec(spec: &str, line: Line) -> Result<Transition, BirdsongError> {
+    let mut parts = spec.split(':');
+    let kind_token = parts.next().unwrap_or("");
+    let kind = match kind_token {
+        "crossfade" => TransitionKind::Crossfade,
+        "fade" => TransitionKind::Fade,
+        "slide_left" => TransitionKind::SlideLeft,
+        "slide_right" => TransitionKind::SlideRight,
+        _ => return Err(BirdsongError::UnknownTransition{line: line.num, column: line.column(kind_token), token: kind_token.to_string()}),
+    };
+    let duration = match parts.next() {
+        Some(duration) => parse_f32(duration, line)?,
+        None => DEFAULT_TRANSITION_DURATION,
+    };
+    let easing = match parts.next() {
+        None | Some("linear") => Easing::Linear,
+        Some("ease_in") => Easing::EaseIn,
+        Some("ease_out") => Easing::EaseOut,
+        Some("ease_in_out") => Easing::EaseInOut,
+        Some(easing) => return Err(BirdsongError::UnknownEasing{line: line.num, column: line.column(easing), token: easing.to_string()}),
+    };
+    Ok(Transition{kind, duration, easing})
+}
+
+fn parse_color(value: &str, line: Line) -> Result<Color, BirdsongError> {
+    let v = parse_floats(value, 4, line)?;
+    Ok(Color::Rgba{red: v[0], green: v[1], blue: v[2], alpha: v[3]})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "## BACKGROUNDS\nforest#images/forest.png@0x0\n## ACTORS\nalice#images/alice.png|sounds/alice.ogg\nalice:angry#images/alice_angry.png\n## ENTRIES\n";
+
+    fn entries(body: &str) -> Vec<Entry> {
+        parse_script(&format!("{}{}", HEADER, body)).unwrap().entries
+    }
+
+    // Errors of a script made only of `body`, which starts at line 1.
+    fn errors(body: &str) -> Vec<BirdsongError> {
+        parse_script(body).unwrap_err().errors
+    }
+
+    fn text(text: &str) -> Entry {
+        Entry::Text{actor: None, expression: None, text: text.to_string(), runs: vec![StyleRun{end: text.len(), style: RunStyle::default()}], controls: Vec::new(), voice: None}
+    }
+
+    #[test]
+    fn parses_sections() {
+        let script = parse_script("## FONTS\nbold#fonts/bold.ttf\n## CURSOR SPRITES\narrow#images/arrow.png\n## BACKGROUNDS\nsky#images/sky.png@10x20|fit:cover|z:-1|parallax:0.5\n## PANELS\nframe#images/frame.png|12\n## ACTORS\nbob#images/bob.png|sounds/bob.ogg|Bob the Builder\n").unwrap();
+        assert_eq!(script.fonts["bold"], "fonts/bold.ttf");
+        assert_eq!(script.cursor_sprites["arrow"], "images/arrow.png");
+        assert_eq!(script.backgrounds["sky"], BackgroundDecl{path: "images/sky.png".to_string(), pos: Vec2::new(10., 20.), fit: BackgroundFit::Cover, z: -1., parallax: 0.5});
+        assert_eq!(script.panels["frame"], PanelDecl{path: "images/frame.png".to_string(), border: 12.});
+        assert_eq!(script.actors["bob"].name, "Bob the Builder");
+        assert_eq!(script.actors["bob"].voices, vec!["sounds/bob.ogg".to_string()]);
+        assert!(script.entries.is_empty());
+    }
+
+    #[test]
+    fn parses_actor_expressions() {
+        let script = parse_script(HEADER).unwrap();
+        let alice = &script.actors["alice"];
+        assert_eq!(alice.name, "alice");
+        assert_eq!(alice.expressions["angry"], "images/alice_angry.png");
+    }
+
+    #[test]
+    fn parses_settings_entries() {
+        assert_eq!(entries("s#font_size:30|box_size:100x200|box_position:1x2x3|font_color:1x0x0x1"), vec![Entry::Settings(vec![
+            Setting::FontSize(30.),
+            Setting::BoxSize(Vec2::new(100., 200.)),
+            Setting::BoxPosition(Vec3::new(1., 2., 3.)),
+            Setting::FontColor(Color::Rgba{red: 1., green: 0., blue: 0., alpha: 1.}),
+        ])]);
+        assert_eq!(entries("s#portrait_transition:crossfade:0.3:ease_out|portrait_transition:none"), vec![Entry::Settings(vec![
+            Setting::PortraitTransition(Some(Transition{kind: TransitionKind::Crossfade, duration: 0.3, easing: Easing::EaseOut})),
+            Setting::PortraitTransition(None),
+        ])]);
+    }
+
+    #[test]
+    fn parses_text_entries() {
+        assert_eq!(entries("t#The wind picks up."), vec![text("The wind picks up.")]);
+        match &entries("t#alice:angry@Who took my tea?")[0] {
+            Entry::Text{actor, expression, text, ..} => {
+                assert_eq!(actor.as_deref(), Some("alice"));
+                assert_eq!(expression.as_deref(), Some("angry"));
+                assert_eq!(text, "Who took my tea?");
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn parses_stage_and_image_entries() {
+        let fade = Transition{kind: TransitionKind::Fade, duration: 1., easing: Easing::Linear};
+        assert_eq!(entries("i#forest\ni#forest|crossfade:1.5:ease_in_out\nenter#alice@left\nenter#alice:angry@1x2x3|slide_left\nexit#alice|fade:1\nhide#background\nhide#portrait"), vec![
+            Entry::Image{name: "forest".to_string(), transition: None},
+            Entry::Image{name: "forest".to_string(), transition: Some(Transition{kind: TransitionKind::Crossfade, duration: 1.5, easing: Easing::EaseInOut})},
+            Entry::Enter{actor: "alice".to_string(), expression: None, slot: StageSlot::Left, transition: None},
+            Entry::Enter{actor: "alice".to_string(), expression: Some("angry".to_string()), slot: StageSlot::Custom(Vec3::new(1., 2., 3.)), transition: Some(Transition{kind: TransitionKind::SlideLeft, duration: DEFAULT_TRANSITION_DURATION, easing: Easing::Linear})},
+            Entry::Exit{actor: "alice".to_string(), transition: Some(fade)},
+            Entry::Hide{target: HideTarget::Background(None), transition: None},
+            Entry::Hide{target: HideTarget::Portrait, transition: None},
+        ]);
+        assert_eq!(entries("hide#background:forest|fade\nhide#background:-1"), vec![
+            Entry::Hide{target: HideTarget::Background(Some(BackgroundLayer::Named("forest".to_string()))), transition: Some(Transition{kind: TransitionKind::Fade, duration: DEFAULT_TRANSITION_DURATION, easing: Easing::Linear})},
+            Entry::Hide{target: HideTarget::Background(Some(BackgroundLayer::Z(-1.))), transition: None},
+        ]);
+    }
+
+    #[test]
+    fn parses_flow_entries() {
+        let parsed = entries("l#start\nset#gold = gold + 1\nif#gold > 2@end\ncmd#give_item|sword|2\nc#Again@start|Leave@end if gold >= 3\ngoto#start\nl#end");
+        assert_eq!(parsed[0], Entry::Label("start".to_string()));
+        assert_eq!(parsed[1], Entry::Set{name: "gold".to_string(), value: parse_expr("gold + 1").unwrap()});
+        assert_eq!(parsed[2], Entry::If{condition: parse_expr("gold > 2").unwrap(), next: 6});
+        assert_eq!(parsed[3], Entry::Command{name: "give_item".to_string(), args: vec!["sword".to_string(), "2".to_string()]});
+        assert_eq!(parsed[4], Entry::Choice(vec![
+            ChoiceItem{text: "Again".to_string(), next: 0, condition: None},
+            ChoiceItem{text: "Leave".to_string(), next: 6, condition: Some(parse_expr("gold >= 3").unwrap())},
+        ]));
+        assert_eq!(parsed[5], Entry::Goto(0));
+        assert_eq!(parsed[6], Entry::Label("end".to_string()));
+    }
+
+    #[test]
+    fn resolves_labels_and_indices() {
+        let script = parse_script(&format!("{}goto#end\nt#Skipped.\nl#end\nc#Back@0|Again@end", HEADER)).unwrap();
+        assert_eq!(script.labels["end"], 2);
+        assert_eq!(script.entries[0], Entry::Goto(2));
+        assert_eq!(script.entries[2], Entry::Label("end".to_string()));
+        match &script.entries[3] {
+            Entry::Choice(items) => assert_eq!(items.iter().map(|item| item.next).collect::<Vec<usize>>(), vec![0, 2]),
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn splits_markup_into_runs() {
+        let red = RunStyle{color: Some(Color::Rgba{red: 1., green: 0., blue: 0., alpha: 1.}), ..RunStyle::default()};
+        let big_wave = RunStyle{size: Some(40.), font: Some("bold".to_string()), wave: true, ..RunStyle::default()};
+        let script = parse_script(&format!("## FONTS\nbold#fonts/bold.ttf\n{}t#A [color=1x0x0x1]red[/color] [size=40][font=bold][wave]big[/wave][/font][/size] [[x]", HEADER)).unwrap();
+        match &script.entries[0] {
+            Entry::Text{text, runs, controls, ..} => {
+                assert_eq!(text, "A red big [x]");
+                assert_eq!(runs, &vec![
+                    StyleRun{end: 2, style: RunStyle::default()},
+                    StyleRun{end: 5, style: red},
+                    StyleRun{end: 6, style: RunStyle::default()},
+                    StyleRun{end: 9, style: big_wave},
+                    StyleRun{end: 13, style: RunStyle::default()},
+                ]);
+                assert!(controls.is_empty());
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn places_control_tags_at_byte_offsets() {
+        let script = parse_script(&format!("{}t#Hé[pause=0.5]llo[speed=20]! [/speed][wait][instant]Bye[/instant][voice=intro]\n## VOICE LINES\nintro#voices/intro.ogg|2.5", HEADER)).unwrap();
+        assert_eq!(script.voice_lines["intro"], VoiceLineDecl{path: "voices/intro.ogg".to_string(), duration: Some(2.5)});
+        match &script.entries[0] {
+            Entry::Text{text, controls, voice, ..} => {
+                assert_eq!(text, "Héllo! Bye");
+                assert_eq!(controls, &vec![
+                    ControlTag{at: 3, control: TypewriterControl::Pause(0.5)},
+                    ControlTag{at: 6, control: TypewriterControl::Speed(Some(20.))},
+                    ControlTag{at: 8, control: TypewriterControl::Speed(None)},
+                    ControlTag{at: 8, control: TypewriterControl::Wait},
+                    ControlTag{at: 8, control: TypewriterControl::Instant(true)},
+                    ControlTag{at: 11, control: TypewriterControl::Instant(false)},
+                ]);
+                assert_eq!(voice.as_deref(), Some("intro"));
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn eases_between_zero_and_one() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.ease(0.), 0., "{:?}", easing);
+            assert_eq!(easing.ease(1.), 1., "{:?}", easing);
+        }
+        assert_eq!(Easing::Linear.ease(0.5), 0.5);
+        assert_eq!(Easing::EaseIn.ease(0.5), 0.25);
+        assert_eq!(Easing::EaseOut.ease(0.5), 0.75);
+        assert_eq!(Easing::EaseInOut.ease(0.5), 0.5);
+    }
+
+    #[test]
+    fn reports_every_error_with_its_position() {
+        let cases: Vec<(&str, BirdsongError)> = vec![
+            ("## ENTRIES\nt", BirdsongError::MissingSeparator{line: 2, column: 1, token: "t".to_string(), separator: '#'}),
+            ("## ENTRIES\nt#Hi [color=1x0x0x1", BirdsongError::MissingSeparator{line: 2, column: 6, token: "[color=1x0x0x1".to_string(), separator: ']'}),
+            ("## ENTRIES\nt#alice@hi@there", BirdsongError::UnexpectedSeparator{line: 2, column: 11, token: "alice@hi@there".to_string(), separator: '@'}),
+            ("## ENTRIES\ns#box_text_speed:fast", BirdsongError::InvalidNumber{line: 2, column: 18, token: "fast".to_string()}),
+            ("## ENTRIES\ns#box_size:1x2x3", BirdsongError::InvalidVector{line: 2, column: 12, token: "1x2x3".to_string(), expected: 2}),
+            ("## ENTRIES\nx#foo", BirdsongError::UnknownEntryType{line: 2, column: 1, token: "x".to_string()}),
+            ("## ENTRIES\ns#bogus:1", BirdsongError::UnknownSetting{line: 2, column: 3, token: "bogus".to_string()}),
+            ("## ENTRIES\nt#bob@Hi", BirdsongError::UnknownReference{line: 2, column: 3, token: "bob".to_string(), kind: "actor"}),
+            ("## ENTRIES\nhide#stage", BirdsongError::UnknownReference{line: 2, column: 6, token: "stage".to_string(), kind: "hide target"}),
+            ("## ENTRIES\nhide#background:sky", BirdsongError::UnknownReference{line: 2, column: 17, token: "sky".to_string(), kind: "background"}),
+            ("## ENTRIES\ngoto#nowhere", BirdsongError::UnknownReference{line: 2, column: 6, token: "nowhere".to_string(), kind: "label"}),
+            ("## ACTORS\nalice#a.png|a.ogg\n## ENTRIES\nt#alice:sad@Hi", BirdsongError::UnknownReference{line: 4, column: 3, token: "alice:sad".to_string(), kind: "expression"}),
+            ("## ENTRIES\nc#Go@99", BirdsongError::InvalidChoiceTarget{line: 2, column: 6, token: "99".to_string()}),
+            ("## ENTRIES\nl#start\nl#start", BirdsongError::DuplicateLabel{line: 3, column: 3, token: "start".to_string()}),
+            ("## ENTRIES\nset#x = 1 +", BirdsongError::InvalidExpression{line: 2, column: 12, token: "".to_string(), reason: "unexpected end of expression".to_string()}),
+            ("## ENTRIES\ncmd#|arg", BirdsongError::InvalidCommand{line: 2, column: 5, token: "|arg".to_string()}),
+            ("## ENTRIES\nt#Hello [blink]world", BirdsongError::UnknownTag{line: 2, column: 10, token: "blink".to_string()}),
+            ("## ENTRIES\nt#Hi[/color]", BirdsongError::UnmatchedTag{line: 2, column: 6, token: "/color".to_string()}),
+            ("## BACKGROUNDS\nforest#bg.png@0x0\n## ENTRIES\ni#forest|spin", BirdsongError::UnknownTransition{line: 4, column: 10, token: "spin".to_string()}),
+            ("## BACKGROUNDS\nforest#bg.png@0x0\n## ENTRIES\ni#forest|fade:1:bouncy", BirdsongError::UnknownEasing{line: 4, column: 17, token: "bouncy".to_string()}),
+            ("## BACKGROUNDS\nforest#bg.png@0x0|fit:tile", BirdsongError::UnknownFitMode{line: 2, column: 23, token: "tile".to_string()}),
+            ("## ACTORS\nalice#a.png|a.ogg\n## BLIPS\nalice#mode:loud", BirdsongError::UnknownBlipMode{line: 4, column: 12, token: "loud".to_string()}),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(errors(source), vec![expected], "in {:?}", source);
+        }
+    }
+
+    #[test]
+    fn rejects_numbers_that_would_stall_the_typewriter() {
+        for number in ["NaN", "inf", "-inf", "-2", "0"] {
+            let expected = BirdsongError::InvalidNumber{line: 2, column: 12, token: number.to_string()};
+            assert_eq!(errors(&format!("## ENTRIES\nt#Hi[speed={}]", number)), vec![expected.clone()], "speed {}", number);
+            assert_eq!(errors(&format!("## ENTRIES\nt#Hi[pause={}]", number)), vec![expected], "pause {}", number);
+            assert_eq!(errors(&format!("## ENTRIES\ns#box_text_speed:{}", number)), vec![BirdsongError::InvalidNumber{line: 2, column: 18, token: number.to_string()}], "box_text_speed {}", number);
+            assert_eq!(errors(&format!("## VOICE LINES\nintro#voices/intro.ogg|{}", number)), vec![BirdsongError::InvalidNumber{line: 2, column: 24, token: number.to_string()}], "voice line {}", number);
+        }
+        assert_eq!(errors("## ENTRIES\ns#box_position:0xNaNx0"), vec![BirdsongError::InvalidNumber{line: 2, column: 18, token: "NaN".to_string()}]);
+        assert!(parse_script("## ENTRIES\ns#box_position:-10x-20x0\nt#Hi[speed=0.5][pause=2]").is_ok());
+    }
+
+    #[test]
+    fn collects_errors_in_line_order() {
+        let errors = errors("## ENTRIES\ns#bogus:1|font_size:big\nt#bob@Hi\nx#foo");
+        assert_eq!(errors.iter().map(|err| (err.line(), err.column())).collect::<Vec<(usize, usize)>>(), vec![(2, 3), (2, 21), (3, 3), (4, 1)]);
+    }
+}